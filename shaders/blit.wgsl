@@ -1,6 +1,5 @@
 struct VSOut {
     @builtin(position) pos: vec4<f32>,
-    @location(0) uv: vec2<f32>,
 };
 
 @vertex
@@ -10,18 +9,167 @@ fn vs(@builtin(vertex_index) idx: u32) -> VSOut {
         vec2( 3.0,  1.0),
         vec2(-1.0,  1.0)
     );
-    var uv = array<vec2<f32>, 3>(
-        vec2(0.0, 2.0),
-        vec2(2.0, 0.0),
-        vec2(0.0, 0.0)
-    );
-    return VSOut(vec4(pos[idx], 0.0, 1.0), uv[idx]);
+    return VSOut(vec4(pos[idx], 0.0, 1.0));
 }
 
+// How the output is encoded; mirrors `Encoding` in gpu.rs.
+const ENCODE_HARDWARE: u32 = 0u; // sRGB surface, encoded on write
+const ENCODE_SRGB: u32 = 1u;     // linear surface, sRGB curve in the shader
+const ENCODE_GAMMA: u32 = 2u;    // linear surface, pow(1 / gamma)
+
+struct Display {
+    exposure: f32,
+    gamma: f32,
+    encoding: u32,
+    // Nonzero draws a one-pixel cross at the center of `region`.
+    crosshair: u32,
+    // Nonzero draws the split-screen divider down the middle.
+    split: u32,
+    // Sharpening of the upscaled trace, 0 (off) to 1.
+    sharpen: f32,
+    // How much the corners darken, 0 to 1, and how far apart the red and
+    // blue channels are there, in percent of the image; see `lens`.
+    vignette: f32,
+    aberration: f32,
+    // The camera projection's z and w rows against view z, (m22, m32,
+    // m23, m33), to turn the trace's linear depth into the depth buffer's.
+    depth_proj: vec4<f32>,
+    // Framebuffer rectangle the trace is shown in, (x, y, width, height) in
+    // pixels; the rest is letterboxed black.
+    region: vec4<f32>,
+};
+
+// Mirrors `Bloom` in bloom.wgsl.
+struct Bloom {
+    threshold: f32,
+    intensity: f32,
+    enabled: u32,
+    _pad: u32,
+};
+
 @group(0) @binding(0) var img:  texture_2d<f32>;
 @group(0) @binding(1) var samp: sampler;
+@group(0) @binding(2) var glow: texture_2d<f32>;
+@group(0) @binding(3) var trace_depth: texture_2d<f32>;
+@group(1) @binding(0) var<uniform> display: Display;
+@group(1) @binding(1) var<uniform> bloom: Bloom;
+
+// Narkowicz's fit of the ACES filmic curve; maps [0, inf) to [0, 1).
+// Keep in sync with `tonemap_aces` in texture.rs.
+fn tonemap_aces(x: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3(0.0), vec3(1.0));
+}
+
+// Keep in sync with `linear_to_srgb` in texture.rs.
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    let lo = c * 12.92;
+    let hi = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+    return select(hi, lo, c <= vec3(0.0031308));
+}
+
+// Arm length and the gap left open around the exact center, in pixels.
+const CROSSHAIR_ARM: f32 = 10.0;
+const CROSSHAIR_GAP: f32 = 3.0;
+
+// Coverage of the crosshair at framebuffer position `pos`: the pixel row
+// and column through the center, so it stays one pixel wide at any
+// resolution scale.
+fn crosshair(pos: vec2<f32>) -> f32 {
+    let d = floor(pos) - floor(display.region.xy + 0.5 * display.region.zw);
+    let on_h = d.y == 0.0 && abs(d.x) <= CROSSHAIR_ARM && abs(d.x) >= CROSSHAIR_GAP;
+    let on_v = d.x == 0.0 && abs(d.y) <= CROSSHAIR_ARM && abs(d.y) >= CROSSHAIR_GAP;
+    return select(0.0, 1.0, on_h || on_v);
+}
+
+// Contrast-adaptive sharpening in the spirit of AMD's CAS: a cross of
+// negative-lobe taps one trace texel away, backed off where the
+// neighbourhood already has strong contrast so edges don't ring. The
+// contrast is taken relative to the brightest tap since the input is HDR.
+fn sharpened(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(img));
+    let c = textureSample(img, samp, uv).rgb;
+    let n = textureSample(img, samp, uv - vec2(0.0, texel.y)).rgb;
+    let s = textureSample(img, samp, uv + vec2(0.0, texel.y)).rgb;
+    let e = textureSample(img, samp, uv + vec2(texel.x, 0.0)).rgb;
+    let w = textureSample(img, samp, uv - vec2(texel.x, 0.0)).rgb;
+    let lo = min(c, min(min(n, s), min(e, w)));
+    let hi = max(c, max(max(n, s), max(e, w)));
+    let amp = sqrt(clamp(lo / max(hi, vec3(1e-4)), vec3(0.0), vec3(1.0)));
+    let lobe = -0.2 * display.sharpen * amp;
+    return max((c + lobe * (n + s + e + w)) / (1.0 + 4.0 * lobe), vec3(0.0));
+}
+
+// The traced depth under `uv` as the depth buffer stores it, so raster
+// overlays drawn after the blit are hidden behind the disk and the shadow.
+fn ndc_depth(uv: vec2<f32>) -> f32 {
+    let dims = vec2<f32>(textureDimensions(trace_depth));
+    let texel = vec2<i32>(min(uv * dims, dims - 1.0));
+    // The camera looks down -z.
+    let z = -textureLoad(trace_depth, texel, 0).r;
+    let p = display.depth_proj;
+    return clamp((p.x * z + p.y) / (p.z * z + p.w), 0.0, 1.0);
+}
+
+// The trace with bloom at `uv`, before exposure.
+fn scene_color(uv: vec2<f32>) -> vec3<f32> {
+    var color: vec3<f32>;
+    if (display.sharpen > 0.0) {
+        color = sharpened(uv);
+    } else {
+        color = textureSample(img, samp, uv).rgb;
+    }
+    if (bloom.enabled != 0u) {
+        color += textureSample(glow, samp, uv).rgb * bloom.intensity;
+    }
+    return color;
+}
+
+// Lens effects, both growing with the squared distance from the image
+// center (1 in the corners): chromatic aberration samples red a little
+// further out and blue a little further in, as a lens bends each
+// wavelength by a different amount, and the vignette darkens toward the
+// corners like light falling off through the barrel.
+fn lens(uv: vec2<f32>) -> vec3<f32> {
+    let d = uv - 0.5;
+    let falloff = 2.0 * dot(d, d);
+    var color = scene_color(uv);
+    if (display.aberration > 0.0) {
+        let shift = 0.01 * display.aberration * falloff * d;
+        color = vec3(scene_color(uv + shift).r, color.g, scene_color(uv - shift).b);
+    }
+    return color * (1.0 - display.vignette * falloff * falloff);
+}
+
+struct FSOut {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+};
 
 @fragment
-fn fs(in: VSOut) -> @location(0) vec4<f32> {
-    return textureSample(img, samp, in.uv);
+fn fs(in: VSOut) -> FSOut {
+    let uv = (in.pos.xy - display.region.xy) / display.region.zw;
+    let hdr = lens(uv) * display.exposure;
+    var ldr = tonemap_aces(hdr);
+    if (display.encoding == ENCODE_SRGB) {
+        ldr = linear_to_srgb(ldr);
+    } else if (display.encoding == ENCODE_GAMMA) {
+        ldr = pow(ldr, vec3(1.0 / display.gamma));
+    }
+    if (display.split != 0u && floor(in.pos.x) == floor(display.region.x + 0.5 * display.region.z)) {
+        ldr = vec3(0.9);
+    }
+    if (display.crosshair != 0u) {
+        // Light on dark areas, dark on bright ones.
+        let ink = select(vec3(0.9), vec3(0.1), dot(ldr, vec3(0.333)) > 0.5);
+        ldr = mix(ldr, ink, crosshair(in.pos.xy));
+    }
+    // Sampled everywhere and masked afterwards, to keep the texture reads
+    // in uniform control flow.
+    let inside = all(uv >= vec2(0.0)) && all(uv <= vec2(1.0));
+    return FSOut(vec4(select(vec3(0.0), ldr, inside), 1.0), select(1.0, ndc_depth(uv), inside));
 }
@@ -0,0 +1,60 @@
+// Bloom: a bright pass from the full-res trace into a half-res texture,
+// followed by a separable Gaussian blur that ping-pongs between two
+// half-res textures. The blit adds the result back before tonemapping.
+
+struct Bloom {
+    threshold: f32,
+    intensity: f32,
+    enabled: u32,
+    _pad: u32,
+};
+
+@group(0) @binding(0) var src: texture_2d<f32>;
+@group(0) @binding(1) var dst: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2) var<uniform> bloom: Bloom;
+
+// 9-tap Gaussian, sigma ~= 2 texels; weights sum to 1.
+const WEIGHTS = array<f32, 5>(0.2270, 0.1946, 0.1216, 0.0541, 0.0162);
+
+fn load_clamped(p: vec2<i32>) -> vec3<f32> {
+    let dims = vec2<i32>(textureDimensions(src));
+    return textureLoad(src, clamp(p, vec2<i32>(0), dims - 1), 0).rgb;
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn bright_pass(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let dims = textureDimensions(dst);
+    if (gid.x >= dims.x || gid.y >= dims.y) { return; }
+
+    // Box-filter the 2x2 source block this texel covers.
+    let base = vec2<i32>(gid.xy) * 2;
+    let color = 0.25 * (load_clamped(base) + load_clamped(base + vec2<i32>(1, 0))
+        + load_clamped(base + vec2<i32>(0, 1)) + load_clamped(base + vec2<i32>(1, 1)));
+
+    // Keep only the part of each pixel brighter than the threshold.
+    let luma = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    let keep = max(luma - bloom.threshold, 0.0) / max(luma, 1e-4);
+    textureStore(dst, vec2<i32>(gid.xy), vec4<f32>(color * keep, 1.0));
+}
+
+fn blur(gid: vec3<u32>, axis: vec2<i32>) {
+    let dims = textureDimensions(dst);
+    if (gid.x >= dims.x || gid.y >= dims.y) { return; }
+
+    let p = vec2<i32>(gid.xy);
+    var sum = load_clamped(p) * WEIGHTS[0];
+    for (var i = 1; i < 5; i++) {
+        sum += (load_clamped(p + axis * i) + load_clamped(p - axis * i)) * WEIGHTS[i];
+    }
+    textureStore(dst, p, vec4<f32>(sum, 1.0));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn blur_h(@builtin(global_invocation_id) gid: vec3<u32>) {
+    blur(gid, vec2<i32>(1, 0));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn blur_v(@builtin(global_invocation_id) gid: vec3<u32>) {
+    blur(gid, vec2<i32>(0, 1));
+}
@@ -0,0 +1,27 @@
+// World-space debug lines (disk edges, the escape sphere, companion holes)
+// drawn over the blit. They test against the depth the blit wrote from the
+// trace, so the disk and the shadow hide what's behind them.
+
+struct DebugGeometry {
+  view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> debug_geometry: DebugGeometry;
+
+struct VsOut {
+  @builtin(position) pos: vec4<f32>,
+  @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs(@location(0) pos: vec3<f32>, @location(1) color: vec3<f32>) -> VsOut {
+  var out: VsOut;
+  out.pos = debug_geometry.view_proj * vec4<f32>(pos, 1.0);
+  out.color = color;
+  return out;
+}
+
+@fragment
+fn fs(in: VsOut) -> @location(0) vec4<f32> {
+  return vec4<f32>(in.color, 1.0);
+}
@@ -0,0 +1,30 @@
+// Orientation gizmo: the world X/Y/Z axes as red/green/blue lines, rotated
+// into view space and drawn into a small corner viewport over the blit.
+
+struct Gizmo {
+  // World-to-view rotation (the view matrix without its translation).
+  view: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> gizmo: Gizmo;
+
+struct VsOut {
+  @builtin(position) pos: vec4<f32>,
+  @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs(@location(0) pos: vec3<f32>, @location(1) color: vec3<f32>) -> VsOut {
+  let v = (gizmo.view * vec4<f32>(pos, 0.0)).xyz;
+  var out: VsOut;
+  // Orthographic: drop view depth, keep a margin inside the viewport.
+  out.pos = vec4<f32>(0.8 * v.xy, 0.5, 1.0);
+  // Axes pointing away from the camera are drawn dimmer.
+  out.color = color * select(1.0, 0.5, v.z < -0.05);
+  return out;
+}
+
+@fragment
+fn fs(in: VsOut) -> @location(0) vec4<f32> {
+  return vec4<f32>(in.color, 1.0);
+}
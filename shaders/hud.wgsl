@@ -0,0 +1,63 @@
+// Text overlay drawn over the blit. Glyphs are 3x5 bitmaps packed into the
+// low 15 bits of a u32, top row first, leftmost pixel in the highest bit.
+
+const COLS: u32 = 16u;
+const ROWS: u32 = 3u;
+// Glyph cell in font pixels: 3x5 glyph plus one pixel of spacing.
+const CELL: vec2<u32> = vec2<u32>(4u, 6u);
+const PAD: f32 = 4.0;
+
+struct Hud {
+  screen: vec2<f32>,
+  origin: vec2<f32>,
+  // Screen pixels per font pixel.
+  scale: f32,
+  _pad0: f32,
+  _pad1: vec2<f32>,
+  // Glyph codes, row-major COLS x ROWS, four per vector.
+  cells: array<vec4<u32>, 12>,
+};
+
+@group(0) @binding(0) var<uniform> hud: Hud;
+
+fn panel_size() -> vec2<f32> {
+  return vec2<f32>(vec2<u32>(COLS, ROWS) * CELL) * hud.scale + 2.0 * PAD;
+}
+
+@vertex
+fn vs(@builtin(vertex_index) vi: u32) -> @builtin(position) vec4<f32> {
+  var corners = array<vec2<f32>, 6>(
+    vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(0.0, 1.0),
+    vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 0.0), vec2<f32>(1.0, 1.0),
+  );
+  let px = hud.origin + corners[vi] * panel_size();
+  let ndc = px / hud.screen * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+  return vec4<f32>(ndc, 0.0, 1.0);
+}
+
+@fragment
+fn fs(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+  var font = array<u32, 18>(
+    0x7b6fu, 0x2c97u, 0x73e7u, 0x73cfu, 0x5bc9u, 0x79cfu, 0x79efu, 0x7249u, 0x7befu, 0x7bcfu, // 0-9
+    0x0002u, 0x0000u, 0x79a4u, 0x7be4u, 0x388eu, 0x5fedu, 0x5aadu, 0x0410u, // . space F P S M X :
+  );
+  let background = vec4<f32>(0.0, 0.0, 0.0, 0.6);
+
+  let local = pos.xy - hud.origin - PAD;
+  if (any(local < vec2<f32>(0.0))) {
+    return background;
+  }
+  let fp = vec2<u32>(local / hud.scale);
+  let cell = fp / CELL;
+  let in_cell = fp % CELL;
+  if (cell.x >= COLS || cell.y >= ROWS || in_cell.x >= 3u || in_cell.y >= 5u) {
+    return background;
+  }
+  let idx = cell.y * COLS + cell.x;
+  let code = min(hud.cells[idx / 4u][idx % 4u], 17u);
+  let bit = 14u - (in_cell.y * 3u + in_cell.x);
+  if (((font[code] >> bit) & 1u) == 0u) {
+    return background;
+  }
+  return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+}
@@ -1,35 +1,728 @@
 struct Camera {
     view_inv: mat4x4<f32>,
     proj_inv: mat4x4<f32>,
-    params: vec4<f32>, // (width, height, time, _pad)
+    params: vec3<f32>, // (width, height, time)
+    _pad0: f32,
 };
 
 @group(0) @binding(0)
-var outputTex: texture_storage_2d<rgba8unorm, write>;
+var outputTex: texture_storage_2d<rgba16float, write>;
 
 @group(0) @binding(1)
 var<uniform> camera: Camera;
 
-fn world_ray_from_pixel(px: vec2<u32>) -> vec3<f32> {
+// Running average of previous frames (read) and the updated average (write);
+// the two textures swap roles every frame.
+@group(0) @binding(2)
+var accumPrev: texture_2d<f32>;
+
+@group(0) @binding(3)
+var accumOut: texture_storage_2d<rgba16float, write>;
+
+// Black-hole parameters in geometric units (G = c = 1); the hole sits at
+// the world origin. Mirrors `SceneUbo`.
+struct Scene {
+    mass: f32,
+    // Flat emitting disk in the y = 0 plane between the two radii.
+    disk_enabled: u32,
+    disk_inner: f32,
+    disk_outer: f32,
+    // Exponent applied to the disk's frequency shift g; 0 disables it.
+    redshift_strength: f32,
+    // 0: physical (intensity ~ g^4), 1: artistic (softer, tinted).
+    redshift_mode: u32,
+    // Integration budget per ray; each step advances `step_size` times the
+    // distance to the horizon.
+    max_steps: u32,
+    step_size: f32,
+    // Kerr spin a/M in [0, 1), about +y (prograde with the disk).
+    spin: f32,
+    // 0: the image; 1: step-count heatmap, 2: final ray direction as RGB,
+    // 3: hit type (see `HIT_*`).
+    debug_mode: u32,
+    // Nonzero: the disk is a slab `disk_thickness` thick whose density is
+    // 3D noise at `noise_scale` cycles per unit, raymarched along the path.
+    volumetric: u32,
+    disk_thickness: f32,
+    noise_scale: f32,
+    // Width of the shadow edge's anti-aliasing ramp in pixels; 0 leaves it
+    // to accumulation.
+    shadow_softness: f32,
+    // Disk temperature in kelvin at the inner and outer edge, used by
+    // `DISK_BLACKBODY`.
+    disk_temp_inner: f32,
+    disk_temp_outer: f32,
+    // Backdrop for escaped rays, one of `SKY_*`; `sky_color` is linear HDR.
+    sky_color: vec3<f32>,
+    sky_mode: u32,
+    // How the disk's color is chosen, one of `DISK_*`.
+    disk_emission: u32,
+    // Nonzero box-filters the photon ring over `ring_filter_width` pixels;
+    // see `filter_photon_ring`.
+    ring_filter: u32,
+    ring_filter_width: f32,
+    _pad1: u32,
+    // How this frame samples the pixel, written every frame apart from the
+    // fields above; mirrors `SamplingUbo`. `frame_index` counts the samples
+    // already in the accumulation history.
+    frame_index: u32,
+    // Thin lens: radius (0 is a pinhole) and distance to the focal plane.
+    aperture: f32,
+    focus_distance: f32,
+    // Nonzero to jitter samples within the pixel for anti-aliasing.
+    jitter: u32,
+    // Varies the sampling pattern; the same seed gives the same image.
+    seed: u32,
+    _pad2: u32,
+    _pad3: u32,
+    _pad4: u32,
+    // World to clip space for the frame in `historyTex`.
+    prev_view_proj: mat4x4<f32>,
+};
+
+// The disk ramp texture, shifted by `apply_redshift`; or a Planck spectrum
+// at the local temperature, shifted in temperature.
+const DISK_RAMP: u32 = 0u;
+const DISK_BLACKBODY: u32 = 1u;
+
+const SKY_BLACK: u32 = 0u;
+const SKY_COLOR: u32 = 1u;
+const SKY_STARS: u32 = 2u;
+const SKY_SKYBOX: u32 = 3u;
+
+// Parameters for the left and right halves of the image; the same unless
+// the split-screen comparison is on.
+@group(0) @binding(4)
+var<uniform> scene_left: Scene;
+
+@group(0) @binding(6)
+var<uniform> scene_right: Scene;
+
+// A pixel whose hit point the CPU wants back, e.g. to orbit around it.
+// Mirrors `PickBuffer` in gpu.rs.
+struct Pick {
+    pixel: vec2<u32>,
+    // Nonzero while a pick is pending; the trace then fills in the rest.
+    requested: u32,
+    hit: u32,
+    position: vec3<f32>,
+    _pad0: f32,
+};
+
+@group(0) @binding(7)
+var<storage, read_write> pick: Pick;
+
+// View-space depth of where each pixel's ray ended, for compositing
+// rasterized geometry over the trace; `FAR_DEPTH` for escaped rays.
+@group(0) @binding(8)
+var depthOut: texture_storage_2d<r32float, write>;
+
+// The previous frame as displayed, for temporal effects: reproject with
+// `scene.prev_view_proj`. Black until the first frame after a resize.
+@group(0) @binding(9)
+var historyTex: texture_2d<f32>;
+
+// This invocation's half, copied in at the start of `main`.
+var<private> scene: Scene;
+
+// Extra non-spinning holes, e.g. for a binary; they bend light but carry no
+// disk. Mirrors `Companions` in scene.rs.
+struct Companion {
+    position: vec3<f32>,
+    mass: f32,
+};
+
+struct Companions {
+    count: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+    holes: array<Companion, 8>,
+};
+
+@group(0) @binding(5)
+var<storage, read> companions: Companions;
+
+// Equirectangular sky: u = longitude about +y, v = polar angle from +y.
+@group(1) @binding(0)
+var background: texture_2d<f32>;
+
+@group(1) @binding(1)
+var backgroundSampler: sampler;
+
+// Cube-map sky for `SKY_SKYBOX`; a 1x1 placeholder when none is loaded.
+@group(1) @binding(2)
+var skybox: texture_cube<f32>;
+
+@group(1) @binding(3)
+var skyboxSampler: sampler;
+
+// Disk color by position across the disk, inner edge at u = 0.
+@group(2) @binding(0)
+var diskRamp: texture_2d<f32>;
+
+@group(2) @binding(1)
+var diskRampSampler: sampler;
+
+const PI: f32 = 3.14159265;
+// Depth written for escaped rays; projects beyond any far plane.
+const FAR_DEPTH: f32 = 1e30;
+// Rays beyond this radius, heading outward, are treated as escaped.
+const ESCAPE_RADIUS: f32 = 100.0;
+
+fn camera_position() -> vec3<f32> {
+    return (camera.view_inv * vec4<f32>(0.0, 0.0, 0.0, 1.0)).xyz;
+}
+
+// Offset of this frame's sample from the pixel center, in [-0.5, 0.5)²,
+// following the R2 low-discrepancy sequence so any run of accumulated
+// frames covers the pixel evenly. Frame 0 samples the center, so the image
+// doesn't shimmer while the camera moves.
+fn pixel_jitter() -> vec2<f32> {
+    if (scene.jitter == 0u || scene.frame_index == 0u) {
+        return vec2<f32>(0.0);
+    }
+    let alpha = vec2<f32>(0.7548777, 0.5698403);
+    // Shifting the whole sequence per seed keeps its even coverage.
+    let shift = random2(vec2<u32>(0u), 0u);
+    return fract(0.5 + shift + f32(scene.frame_index) * alpha) - 0.5;
+}
+
+struct Ray {
+    origin: vec3<f32>,
+    dir: vec3<f32>,
+};
+
+fn unproject(ndc: vec3<f32>) -> vec3<f32> {
+    let view_pos = camera.proj_inv * vec4<f32>(ndc, 1.0);
+    return (camera.view_inv * vec4<f32>(view_pos.xyz / view_pos.w, 1.0)).xyz;
+}
+
+// Where in the image this frame samples pixel `px`, in pixels.
+fn sample_position(px: vec2<u32>) -> vec2<f32> {
+    return vec2<f32>(px) + vec2<f32>(0.5, 0.5) + pixel_jitter();
+}
+
+// The ray through `pos` (in pixels) runs from its point on the near plane
+// to the one on the far plane, so it works for perspective (rays fan out
+// from the eye) and orthographic (parallel rays) projections alike.
+fn world_ray_at(pos: vec2<f32>) -> Ray {
     let dims = textureDimensions(outputTex);
-    let uv = (vec2<f32>(px) + vec2<f32>(0.5, 0.5)) / vec2<f32>(f32(dims.x), f32(dims.y));
-    let ndc = vec3<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 1.0);
-    let clip = vec4<f32>(ndc, 1.0);
+    let uv = pos / vec2<f32>(f32(dims.x), f32(dims.y));
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let near = unproject(vec3<f32>(ndc, 0.0));
+    let far = unproject(vec3<f32>(ndc, 1.0));
+    return Ray(near, normalize(far - near));
+}
 
-    let view_pos = camera.proj_inv * clip;
-    let view_pos3 = view_pos.xyz / view_pos.w;
+// PCG hash; decorrelates per-pixel, per-frame random numbers.
+fn hash(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random2(px: vec2<u32>, frame: u32) -> vec2<f32> {
+    let a = hash(px.x ^ hash(px.y ^ hash(frame ^ hash(scene.seed))));
+    let b = hash(a);
+    return vec2<f32>(f32(a), f32(b)) / 4294967295.0;
+}
+
+// Moves the ray's start to a random point on the lens, keeping it aimed at
+// the same point on the focal plane: anything off that plane blurs as the
+// samples accumulate.
+fn apply_lens(ray: Ray, px: vec2<u32>) -> Ray {
+    let right = camera.view_inv[0].xyz;
+    let up = camera.view_inv[1].xyz;
+    let forward = -camera.view_inv[2].xyz;
+    let focus = ray.origin + ray.dir * (scene.focus_distance / dot(ray.dir, forward));
+    let u = random2(px, scene.frame_index);
+    // Uniform over the disk.
+    let radius = scene.aperture * sqrt(u.x);
+    let angle = 2.0 * PI * u.y;
+    let origin = ray.origin + radius * (cos(angle) * right + sin(angle) * up);
+    return Ray(origin, normalize(focus - origin));
+}
+
+// Null geodesics of the Schwarzschild metric, written in Cartesian form:
+// with h = |x × v| conserved, x'' = -3/2 r_s h² x / r⁵ traces exactly the
+// photon orbit equation u'' + u = 3/2 r_s u² (u = 1/r).
+//
+// Spin is folded in approximately rather than integrating the Kerr metric:
+// r_s is rescaled so the photon sphere sits at the Kerr equatorial photon
+// orbit for the ray's prograde/retrograde sense (down to M prograde, out to
+// 4M retrograde as a/M -> 1), which flattens the shadow on one side, and a
+// frame-dragging term rotates the ray about +y at the ZAMO angular velocity
+// ω = 2J/r³ (J = aM). With spin 0 both reduce to the Schwarzschild case.
+fn geodesic_accel(p: vec3<f32>, v: vec3<f32>) -> vec3<f32> {
+    let m = scene.mass;
+    let r2 = dot(p, p);
+    let r = sqrt(r2);
+    let l = cross(p, v);
+    let h2 = dot(l, l);
+    var rs = 2.0 * m;
+    var drag = vec3<f32>(0.0);
+    if (scene.spin > 0.0) {
+        rs = photon_orbit_radius(l) / 1.5;
+        let omega = 2.0 * scene.spin * m * m / (r2 * r);
+        drag = cross(vec3<f32>(0.0, omega, 0.0), v);
+    }
+    var accel = -1.5 * rs * h2 * p / (r2 * r2 * r) + drag;
+    // Companions add their own Schwarzschild terms, each about its centre.
+    for (var i = 0u; i < companions.count; i++) {
+        let hole = companions.holes[i];
+        let d = p - hole.position;
+        let d2 = dot(d, d);
+        let dl = cross(d, v);
+        accel += -3.0 * hole.mass * dot(dl, dl) * d / (d2 * d2 * sqrt(d2));
+    }
+    return accel;
+}
+
+// Radius of the photon orbit `geodesic_accel` bends a ray with angular
+// momentum `l` around: the Kerr equatorial one for its sense of rotation,
+// 3M without spin.
+fn photon_orbit_radius(l: vec3<f32>) -> f32 {
+    // +1 for rays circling with the hole, -1 against it.
+    let sense = l.y / max(length(l), 1e-6);
+    return 2.0 * scene.mass * (1.0 + cos(2.0 / 3.0 * acos(-scene.spin * sense)));
+}
+
+// A ray's impact parameter about the primary hole; it falls in below
+// √3 times its photon orbit radius (3√3 M without spin).
+fn impact_parameter(ray: Ray) -> f32 {
+    return length(cross(ray.origin, ray.dir));
+}
+
+fn critical_impact(ray: Ray) -> f32 {
+    return sqrt(3.0) * photon_orbit_radius(cross(ray.origin, ray.dir));
+}
 
-    let world_pos = camera.view_inv * vec4<f32>(view_pos3, 1.0);
-    let cam_pos   = (camera.view_inv * vec4<f32>(0.0, 0.0, 0.0, 1.0)).xyz;
-    return normalize(world_pos.xyz - cam_pos);
+// Distance from `p` to the nearest companion's horizon, or a large value
+// with none; `captured` is set if `p` is inside one.
+fn companion_clearance(p: vec3<f32>, captured: ptr<function, bool>) -> f32 {
+    var clearance = 1e9;
+    for (var i = 0u; i < companions.count; i++) {
+        let hole = companions.holes[i];
+        let gap = length(p - hole.position) - 2.0 * hole.mass;
+        if (gap <= 0.0) {
+            *captured = true;
+        }
+        clearance = min(clearance, gap);
+    }
+    return clearance;
 }
 
-@compute @workgroup_size(8, 8, 1)
+// Sky seen along an escaped ray's final direction.
+fn sky(dir: vec3<f32>) -> vec3<f32> {
+    if (scene.sky_mode == SKY_BLACK) {
+        return vec3<f32>(0.0);
+    }
+    if (scene.sky_mode == SKY_COLOR) {
+        return scene.sky_color;
+    }
+    if (scene.sky_mode == SKY_SKYBOX && textureDimensions(skybox).x > 1u) {
+        return textureSampleLevel(skybox, skyboxSampler, dir, 0.0).rgb;
+    }
+    let u = 0.5 + atan2(dir.z, dir.x) / (2.0 * PI);
+    let v = acos(clamp(dir.y, -1.0, 1.0)) / PI;
+    return textureSampleLevel(background, backgroundSampler, vec2<f32>(u, v), 0.0).rgb;
+}
+
+// Emission of the disk at radius `r`: hot and bright at the inner edge,
+// fading outward. Values above 1 are left for the tonemapper and bloom.
+fn disk_emission(r: f32) -> vec3<f32> {
+    let falloff = pow(scene.disk_inner / r, 2.0);
+    let t = clamp((r - scene.disk_inner) / (scene.disk_outer - scene.disk_inner), 0.0, 1.0);
+    let color = textureSampleLevel(diskRamp, diskRampSampler, vec2<f32>(t, 0.5), 0.0).rgb;
+    return 4.0 * falloff * color;
+}
+
+// Ratio of observed to emitted frequency for light leaving the disk at
+// `hit` towards the camera, travelling along `photon_dir`: Doppler shift
+// from the Keplerian orbit times the gravitational shift between the
+// emitter's and the (static) camera's radius.
+fn disk_frequency_shift(hit: vec3<f32>, photon_dir: vec3<f32>) -> f32 {
+    let m = scene.mass;
+    let r = length(hit.xz);
+    // Orbital speed seen by a static observer; prograde about +y.
+    let beta = min(sqrt(m / max(r - 2.0 * m, 1e-4)), 0.99);
+    let gamma = 1.0 / sqrt(1.0 - beta * beta);
+    let orbit_dir = normalize(vec3<f32>(hit.z, 0.0, -hit.x));
+    let doppler = 1.0 / (gamma * (1.0 - beta * dot(orbit_dir, photon_dir)));
+    let r_cam = length(camera_position());
+    let gravity = sqrt(max(1.0 - 2.0 * m / r, 0.0) / max(1.0 - 2.0 * m / r_cam, 1e-4));
+    return doppler * gravity;
+}
+
+// Wavelengths in micrometres at which the Planck curve is sampled for each
+// of red, green and blue, averaged over the band.
+const RED_BAND: vec3<f32> = vec3<f32>(0.58, 0.61, 0.64);
+const GREEN_BAND: vec3<f32> = vec3<f32>(0.52, 0.55, 0.58);
+const BLUE_BAND: vec3<f32> = vec3<f32>(0.44, 0.465, 0.49);
+// The second radiation constant hc/k, in micrometre-kelvin.
+const PLANCK_C2: f32 = 14388.0;
+// Colour temperature that comes out white (D65's).
+const WHITE_KELVIN: f32 = 6504.0;
+
+// Planck's law up to a constant factor, for wavelengths `lambda` in um.
+fn planck(lambda: vec3<f32>, kelvin: f32) -> vec3<f32> {
+    let l5 = lambda * lambda * lambda * lambda * lambda;
+    return 1.0 / (l5 * (exp(PLANCK_C2 / (lambda * kelvin)) - 1.0));
+}
+
+// Mean spectral radiance of a blackbody in each color band.
+fn planck_bands(kelvin: f32) -> vec3<f32> {
+    let third = 1.0 / 3.0;
+    return vec3<f32>(
+        dot(planck(RED_BAND, kelvin), vec3<f32>(third)),
+        dot(planck(GREEN_BAND, kelvin), vec3<f32>(third)),
+        dot(planck(BLUE_BAND, kelvin), vec3<f32>(third)),
+    );
+}
+
+// Linear RGB of a blackbody at `kelvin`, white-balanced to `WHITE_KELVIN`
+// and scaled so its brightest channel is 1.
+fn blackbody_color(kelvin: f32) -> vec3<f32> {
+    let rgb = planck_bands(kelvin) / planck_bands(WHITE_KELVIN);
+    return rgb / max(max(rgb.r, max(rgb.g, rgb.b)), 1e-20);
+}
+
+// Blackbody emission at radius `r` seen with frequency shift `g`. The
+// temperature follows a power law in r through the two edge temperatures
+// (a thin disk has T ~ r^-3/4). A shifted blackbody is again a blackbody,
+// at g times the temperature, and its total intensity goes as T^4; it
+// matches the ramp's brightness at an unshifted inner edge.
+fn blackbody_emission(r: f32, g: f32) -> vec3<f32> {
+    let t = log(max(r, scene.disk_inner) / scene.disk_inner) / log(scene.disk_outer / scene.disk_inner);
+    let kelvin = scene.disk_temp_inner * pow(scene.disk_temp_outer / scene.disk_temp_inner, t);
+    let observed = max(g * kelvin, 1.0);
+    let brightness = pow(observed / scene.disk_temp_inner, 4.0);
+    return 4.0 * brightness * blackbody_color(observed);
+}
+
+// Observed color of the disk at radius `r` with frequency shift `g`.
+fn disk_color(r: f32, g: f32) -> vec3<f32> {
+    if (scene.disk_emission == DISK_BLACKBODY) {
+        return blackbody_emission(r, pow(g, scene.redshift_strength));
+    }
+    return apply_redshift(disk_emission(r), g);
+}
+
+// Applies the frequency shift `g` to `color`, raised to `redshift_strength`.
+fn apply_redshift(color: vec3<f32>, g: f32) -> vec3<f32> {
+    let shift = pow(g, scene.redshift_strength);
+    let blue = vec3<f32>(0.6, 0.8, 1.4);
+    let red = vec3<f32>(1.4, 0.6, 0.35);
+    if (scene.redshift_mode == 0u) {
+        // Specific intensity scales as g^3 and integrated intensity as g^4;
+        // the hue follows the shift only gently.
+        let tint = mix(red, blue, clamp(0.5 + 0.5 * (shift - 1.0), 0.0, 1.0));
+        return color * pow(shift, 4.0) * mix(vec3<f32>(1.0), tint, 0.35);
+    }
+    // Artistic: keep brightness in a readable range and lean on the hue.
+    let tint = mix(red, blue, clamp(0.5 + (shift - 1.0), 0.0, 1.0));
+    return color * sqrt(shift) * tint;
+}
+
+// Optical depth per unit length of the volumetric disk at full density.
+const VOLUME_DENSITY: f32 = 12.0;
+
+// Trilinearly interpolated lattice noise in [0, 1].
+fn value_noise(p: vec3<f32>) -> f32 {
+    let cell = floor(p);
+    let f = p - cell;
+    let u = f * f * (3.0 - 2.0 * f);
+    let c = vec3<i32>(cell);
+    var corners: array<f32, 8>;
+    for (var i = 0; i < 8; i++) {
+        let o = vec3<i32>(i & 1, (i >> 1) & 1, (i >> 2) & 1);
+        let k = vec3<u32>(c + o);
+        corners[i] = f32(hash(k.x ^ hash(k.y ^ hash(k.z)))) / 4294967295.0;
+    }
+    let x0 = mix(mix(corners[0], corners[1], u.x), mix(corners[2], corners[3], u.x), u.y);
+    let x1 = mix(mix(corners[4], corners[5], u.x), mix(corners[6], corners[7], u.x), u.y);
+    return mix(x0, x1, u.z);
+}
+
+// Density of the volumetric disk at `p` in [0, 1]: three octaves of noise,
+// thresholded into wisps, under a profile that thins towards the slab's
+// faces and the disk's edges.
+fn disk_density(p: vec3<f32>) -> f32 {
+    let r = length(p.xz);
+    let half_h = 0.5 * scene.disk_thickness;
+    if (abs(p.y) >= half_h || r < scene.disk_inner || r > scene.disk_outer) {
+        return 0.0;
+    }
+    let y = p.y / half_h;
+    let edge = min(r - scene.disk_inner, scene.disk_outer - r) / (scene.disk_outer - scene.disk_inner);
+    let profile = (1.0 - y * y) * smoothstep(0.0, 0.1, edge);
+    let q = p * scene.noise_scale;
+    let n = 0.57 * value_noise(q) + 0.29 * value_noise(2.03 * q) + 0.14 * value_noise(4.01 * q);
+    return profile * smoothstep(0.35, 0.75, n);
+}
+
+// How a traced ray ended, for the hit-type debug view.
+const HIT_SKY: u32 = 0u;
+const HIT_HORIZON: u32 = 1u;
+const HIT_DISK: u32 = 2u;
+const HIT_OUT_OF_STEPS: u32 = 3u;
+
+struct Trace {
+    color: vec3<f32>,
+    // Integration steps taken, the ray's last direction and how it ended.
+    steps: u32,
+    dir: vec3<f32>,
+    hit: u32,
+    // Where it ended: the disk hit point for `HIT_DISK`.
+    pos: vec3<f32>,
+};
+
+// Follows one photon from the camera with RK4, returning the radiance it
+// picks up: the disk if it crosses it, black if it falls through the
+// horizon, the sky if it escapes, plus what the debug views need.
+fn trace_ray(origin: vec3<f32>, dir: vec3<f32>) -> Trace {
+    let m = scene.mass;
+    // Outer Kerr horizon; 2M without spin.
+    let rs = m * (1.0 + sqrt(1.0 - scene.spin * scene.spin));
+    let volumetric = scene.volumetric != 0u;
+    let half_h = 0.5 * scene.disk_thickness;
+    // Light picked up inside the volumetric disk, and how much of whatever
+    // lies behind it still gets through.
+    var glow = vec3<f32>(0.0);
+    var transmittance = 1.0;
+    var p = origin;
+    var v = dir;
+    for (var i = 0u; i < scene.max_steps; i++) {
+        let r = length(p);
+        var captured = r <= rs;
+        let clearance = min(r - rs, companion_clearance(p, &captured));
+        if (captured) {
+            return Trace(glow, i, v, HIT_HORIZON, p);
+        }
+        if (r > ESCAPE_RADIUS && dot(p, v) > 0.0) {
+            return Trace(glow + transmittance * sky(normalize(v)), i, v, HIT_SKY, p);
+        }
+        // Step length grows with distance: fine near a hole, coarse far away.
+        var dt = scene.step_size * max(clearance, 0.02);
+        // Resolve the slab's structure instead of stepping over it.
+        let near_slab = abs(p.y) < 2.0 * half_h && length(p.xz) < scene.disk_outer + half_h;
+        if (volumetric && scene.disk_enabled != 0u && near_slab) {
+            dt = min(dt, 0.35 * half_h);
+        }
+        let k1v = geodesic_accel(p, v);
+        let k1p = v;
+        let k2p = v + 0.5 * dt * k1v;
+        let k2v = geodesic_accel(p + 0.5 * dt * k1p, k2p);
+        let k3p = v + 0.5 * dt * k2v;
+        let k3v = geodesic_accel(p + 0.5 * dt * k2p, k3p);
+        let k4p = v + dt * k3v;
+        let k4v = geodesic_accel(p + dt * k3p, k4p);
+        let next = p + dt / 6.0 * (k1p + 2.0 * k2p + 2.0 * k3p + k4p);
+        v += dt / 6.0 * (k1v + 2.0 * k2v + 2.0 * k3v + k4v);
+
+        if (scene.disk_enabled != 0u && volumetric) {
+            // Emission-absorption at the step's midpoint.
+            let mid = 0.5 * (p + next);
+            let density = disk_density(mid);
+            if (density > 0.0) {
+                let alpha = 1.0 - exp(-VOLUME_DENSITY * density * length(next - p));
+                let g = disk_frequency_shift(mid, -normalize(v));
+                let emission = disk_color(length(mid.xz), g);
+                glow += transmittance * alpha * emission;
+                transmittance *= 1.0 - alpha;
+                if (transmittance < 0.01) {
+                    return Trace(glow, i + 1u, v, HIT_DISK, mid);
+                }
+            }
+        } else if (scene.disk_enabled != 0u && p.y * next.y <= 0.0 && p.y != next.y) {
+            // Disk hit: the step crossed the equatorial plane within the radii.
+            let hit = mix(p, next, p.y / (p.y - next.y));
+            let r_hit = length(hit.xz);
+            if (r_hit >= scene.disk_inner && r_hit <= scene.disk_outer) {
+                let g = disk_frequency_shift(hit, -normalize(v));
+                return Trace(disk_color(r_hit, g), i + 1u, v, HIT_DISK, hit);
+            }
+        }
+        p = next;
+    }
+    // Out of steps: outbound rays are close enough to escaping; the rest are
+    // still winding around the photon sphere and count as captured.
+    if (dot(p, v) > 0.0) {
+        return Trace(glow + transmittance * sky(normalize(v)), scene.max_steps, v, HIT_OUT_OF_STEPS, p);
+    }
+    return Trace(glow, scene.max_steps, v, HIT_OUT_OF_STEPS, p);
+}
+
+// Fell into a hole, or was still heading inward when the steps ran out.
+fn captured(trace: Trace) -> bool {
+    return trace.hit == HIT_HORIZON || (trace.hit == HIT_OUT_OF_STEPS && dot(trace.pos, trace.dir) <= 0.0);
+}
+
+// Change in impact parameter per pixel, across and down the image: the
+// compute shader's stand-in for dpdx/dpdy, from finite differences of the
+// camera rays.
+fn impact_gradient(pos: vec2<f32>) -> vec2<f32> {
+    let b0 = impact_parameter(world_ray_at(pos));
+    return vec2<f32>(
+        impact_parameter(world_ray_at(pos + vec2<f32>(1.0, 0.0))),
+        impact_parameter(world_ray_at(pos + vec2<f32>(0.0, 1.0))),
+    ) - b0;
+}
+
+// Rays within this fraction of the critical impact parameter outside it
+// make up the photon ring, and are filtered with this many taps.
+const RING_BAND = 0.05;
+const RING_TAPS = 4u;
+
+// Just outside the shadow, rays wind round the hole before escaping or
+// hitting the disk from behind, and the number of turns changes many times
+// within one pixel: a single sample there flickers between ring and sky
+// from frame to frame. Pixels in that band are box-filtered instead,
+// averaging `RING_TAPS` rays spread across `ring_filter_width` pixels
+// along the impact parameter's gradient, the one direction the ring
+// varies in.
+fn in_photon_ring(ray: Ray) -> bool {
+    let b = impact_parameter(ray);
+    let critical = critical_impact(ray);
+    return b >= critical && b <= critical * (1.0 + RING_BAND);
+}
+
+fn filter_photon_ring(pos: vec2<f32>, px: vec2<u32>) -> vec3<f32> {
+    let across = normalize(impact_gradient(pos) + vec2<f32>(1e-9, 0.0));
+    var sum = vec3<f32>(0.0);
+    for (var i = 0u; i < RING_TAPS; i++) {
+        let offset = ((f32(i) + 0.5) / f32(RING_TAPS) - 0.5) * scene.ring_filter_width;
+        var tap = world_ray_at(pos + offset * across);
+        if (scene.aperture > 0.0) {
+            tap = apply_lens(tap, px);
+        }
+        sum += trace_ray(tap.origin, tap.dir).color;
+    }
+    return sum / f32(RING_TAPS);
+}
+
+// Anti-aliases the shadow's edge analytically instead of waiting for
+// accumulation. The fraction of the pixel outside the shadow ramps from 0
+// to 1 as the impact parameter crosses the critical one, over
+// `shadow_softness` pixels; inside the ramp a second ray is traced just
+// across the edge and the two sides blend by that coverage. Outside is
+// usually the photon ring, rays that wind round and hit the disk from
+// behind. Frame dragging and companions shift the real edge from the
+// analytic one; where the second ray doesn't land on the other side, the
+// pixel stays as traced.
+fn soften_shadow_edge(pos: vec2<f32>, px: vec2<u32>, ray: Ray, trace: Trace) -> vec3<f32> {
+    let b = impact_parameter(ray);
+    let critical = critical_impact(ray);
+    let db = impact_gradient(pos);
+    let footprint = max(length(db), 1e-6);
+    let width = scene.shadow_softness * footprint;
+    let coverage = clamp((b - critical) / width + 0.5, 0.0, 1.0);
+    if (coverage <= 0.0 || coverage >= 1.0) {
+        return trace.color;
+    }
+    // Half a ramp beyond the edge on the other side.
+    let inside = captured(trace);
+    let target_b = critical + select(-0.5, 0.5, inside) * width;
+    var other_ray = world_ray_at(pos + (target_b - b) * db / (footprint * footprint));
+    if (scene.aperture > 0.0) {
+        other_ray = apply_lens(other_ray, px);
+    }
+    let other = trace_ray(other_ray.origin, other_ray.dir);
+    if (captured(other) == inside) {
+        return trace.color;
+    }
+    if (inside) {
+        return mix(trace.color, other.color, coverage);
+    }
+    return mix(other.color, trace.color, coverage);
+}
+
+// Blue through green and yellow to red over [0, 1].
+fn heatmap(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0);
+    let rgb = vec3<f32>(2.0 * x - 0.5, 1.5 - 1.5 * abs(2.0 * x - 1.0), 1.0 - 2.0 * x);
+    return clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// The pixel's color for `scene.debug_mode`. Debug views stay within [0, 1]
+// and go through the blit like the image does.
+fn shade(trace: Trace) -> vec3<f32> {
+    switch (scene.debug_mode) {
+        case 1u: {
+            return heatmap(f32(trace.steps) / f32(scene.max_steps));
+        }
+        case 2u: {
+            return 0.5 + 0.5 * normalize(trace.dir);
+        }
+        case 3u: {
+            var palette = array<vec3<f32>, 4>(
+                vec3<f32>(0.2, 0.4, 1.0), // sky
+                vec3<f32>(0.1, 0.1, 0.1), // horizon
+                vec3<f32>(1.0, 0.6, 0.1), // disk
+                vec3<f32>(1.0, 0.0, 1.0), // out of steps
+            );
+            return palette[trace.hit];
+        }
+        default: {
+            return trace.color;
+        }
+    }
+}
+
+// Distance along the view axis to where the ray ended: the disk hit or the
+// horizon. Rays that got away are behind everything a raster pass draws.
+fn view_depth(trace: Trace) -> f32 {
+    if (trace.hit == HIT_SKY || (trace.hit == HIT_OUT_OF_STEPS && !captured(trace))) {
+        return FAR_DEPTH;
+    }
+    let forward = -camera.view_inv[2].xyz;
+    return max(dot(trace.pos - camera_position(), forward), 0.0);
+}
+
+// Tile size, picked per GPU at startup and set through these overrides;
+// see `pick_workgroup_size` in gpu.rs.
+override WORKGROUP_X: u32 = 8u;
+override WORKGROUP_Y: u32 = 8u;
+
+@compute @workgroup_size(WORKGROUP_X, WORKGROUP_Y, 1)
 fn main(@builtin(global_invocation_id) gid: vec3<u32>) {
     let dims = textureDimensions(outputTex);
     if (gid.x >= dims.x || gid.y >= dims.y) { return; }
+    if (gid.x < dims.x / 2u) {
+        scene = scene_left;
+    } else {
+        scene = scene_right;
+    }
+
+    let pos = sample_position(gid.xy);
+    var ray = world_ray_at(pos);
+    if (scene.aperture > 0.0) {
+        ray = apply_lens(ray, gid.xy);
+    }
+    var trace = trace_ray(ray.origin, ray.dir);
+    // The ring filter's taps reach into the shadow too, so it takes the
+    // place of edge softening where it applies.
+    if (scene.ring_filter != 0u && in_photon_ring(ray)) {
+        trace.color = filter_photon_ring(pos, gid.xy);
+    } else if (scene.shadow_softness > 0.0) {
+        trace.color = soften_shadow_edge(pos, gid.xy, ray, trace);
+    }
+    let sample = shade(trace);
+    if (pick.requested != 0u && all(gid.xy == pick.pixel)) {
+        pick.hit = trace.hit;
+        pick.position = trace.pos;
+    }
+    let px = vec2<i32>(i32(gid.x), i32(gid.y));
+    textureStore(depthOut, px, vec4<f32>(view_depth(trace), 0.0, 0.0, 0.0));
 
-    let dir = world_ray_from_pixel(gid.xy);
-    let rgb = 0.5 * (dir + vec3<f32>(1.0, 1.0, 1.0));
-    textureStore(outputTex, vec2<i32>(i32(gid.x), i32(gid.y)), vec4<f32>(rgb, 1.0));
+    // Incremental mean: with n previous samples, avg' = avg + (x - avg) / (n + 1).
+    let n = f32(scene.frame_index);
+    var avg = sample;
+    if (scene.frame_index > 0u) {
+        let prev = textureLoad(accumPrev, px, 0).rgb;
+        avg = prev + (sample - prev) / (n + 1.0);
+    }
+    textureStore(accumOut, px, vec4<f32>(avg, 1.0));
+    textureStore(outputTex, px, vec4<f32>(avg, 1.0));
 }
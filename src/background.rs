@@ -0,0 +1,222 @@
+use std::f32::consts::PI;
+use std::path::Path;
+
+use half::f16;
+
+// Size of the generated star map; 2:1 like any equirectangular image.
+const PROCEDURAL_SIZE: (u32, u32) = (2048, 1024);
+const PROCEDURAL_STARS: u32 = 9000;
+// Skybox face files, in wgpu's cube layer order.
+const SKYBOX_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+/// An equirectangular sky in linear RGBA16F: u = longitude around +y,
+/// v = polar angle from +y, matching `sky` in trace.wgsl.
+pub(crate) struct Background {
+    width: u32,
+    height: u32,
+    texels: Vec<[f16; 4]>,
+}
+
+pub(crate) fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Reads a PNG (sRGB) or Radiance HDR (linear) image as linear texels.
+fn load_texels(path: &Path, max_size: u32) -> Result<(u32, u32, Vec<[f16; 4]>), String> {
+    let img = image::open(path).map_err(|e| e.to_string())?;
+    let (width, height) = (img.width(), img.height());
+    if width > max_size || height > max_size {
+        return Err(format!("{width}x{height} exceeds the GPU's {max_size}px texture limit"));
+    }
+    // 8/16-bit images store sRGB-encoded values; float ones are linear.
+    let linearize = !matches!(
+        img.color(),
+        image::ColorType::Rgb32F | image::ColorType::Rgba32F
+    );
+    let texels = img
+        .into_rgba32f()
+        .pixels()
+        .map(|px| {
+            let [r, g, b, _] = px.0.map(|c| if linearize { srgb_to_linear(c) } else { c });
+            [r, g, b, 1.0].map(f16::from_f32)
+        })
+        .collect();
+    Ok((width, height, texels))
+}
+
+impl Background {
+    /// Loads a PNG (sRGB) or Radiance HDR (linear) star map.
+    pub(crate) fn load(path: &Path, max_size: u32) -> Result<Self, String> {
+        let (width, height, texels) = load_texels(path, max_size)?;
+        Ok(Self { width, height, texels })
+    }
+
+    /// A deterministic field of point stars, spread uniformly over the
+    /// sphere, with a power-law brightness distribution and a mild color
+    /// spread from red to blue-white.
+    pub(crate) fn procedural() -> Self {
+        let (width, height) = PROCEDURAL_SIZE;
+        let mut rgb = vec![[0.0f32; 3]; (width * height) as usize];
+        // xorshift32: no need for a real RNG, only a stable pattern.
+        let mut state = 0x9e37_79b9_u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32
+        };
+        for _ in 0..PROCEDURAL_STARS {
+            let cos_theta = 2.0 * next() - 1.0;
+            let phi = 2.0 * PI * next();
+            let u = phi / (2.0 * PI);
+            let v = cos_theta.acos() / PI;
+            let x = ((u * width as f32) as u32).min(width - 1);
+            let y = ((v * height as f32) as u32).min(height - 1);
+            let brightness = 0.05 * next().powf(-0.8).min(60.0);
+            let warmth = next();
+            let color = [1.0, 0.85 + 0.15 * warmth, 0.6 + 0.5 * warmth];
+            let texel = &mut rgb[(y * width + x) as usize];
+            for (c, tint) in texel.iter_mut().zip(color) {
+                *c += brightness * tint;
+            }
+        }
+        let texels = rgb
+            .into_iter()
+            .map(|[r, g, b]| [r, g, b, 1.0].map(f16::from_f32))
+            .collect();
+        Self { width, height, texels }
+    }
+
+    pub(crate) fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let size = wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        };
+        let tex = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("background_tex"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &tex,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&self.texels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width * 8),
+                rows_per_image: Some(self.height),
+            },
+            size,
+        );
+        let view = tex.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("background_tex"),
+            ..Default::default()
+        });
+        (tex, view)
+    }
+}
+
+/// A cube-map sky in linear RGBA16F, an alternative to the equirectangular
+/// one without its pinched poles. `faces` holds the six square faces back
+/// to back, in `SKYBOX_FACES` order.
+pub(crate) struct Skybox {
+    size: u32,
+    faces: Vec<[f16; 4]>,
+}
+
+impl Skybox {
+    /// Loads `px.png` through `nz.png` from `dir`; all six must be the same
+    /// square size.
+    pub(crate) fn load(dir: &Path, max_size: u32) -> Result<Self, String> {
+        let mut size = None;
+        let mut faces = Vec::new();
+        for name in SKYBOX_FACES {
+            let path = dir.join(format!("{name}.png"));
+            let (width, height, texels) =
+                load_texels(&path, max_size).map_err(|e| format!("{}: {e}", path.display()))?;
+            if width != height || size.is_some_and(|size| size != width) {
+                return Err(format!("{name}.png is {width}x{height}; faces must be equal squares"));
+            }
+            size = Some(width);
+            faces.extend(texels);
+        }
+        Ok(Self { size: size.unwrap_or(1), faces })
+    }
+
+    /// A 1x1 black cube, bound when no skybox is loaded. The trace shader
+    /// takes its size as the cue to sample the equirectangular sky instead.
+    pub(crate) fn placeholder() -> Self {
+        Self { size: 1, faces: vec![[f16::ZERO, f16::ZERO, f16::ZERO, f16::ONE]; 6] }
+    }
+
+    pub(crate) fn is_placeholder(&self) -> bool {
+        self.size == 1
+    }
+
+    pub(crate) fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let tex = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("skybox_tex"),
+            size: wgpu::Extent3d {
+                width: self.size,
+                height: self.size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let face_len = (self.size * self.size) as usize;
+        for (layer, face) in self.faces.chunks_exact(face_len).enumerate() {
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &tex,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                bytemuck::cast_slice(face),
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.size * 8),
+                    rows_per_image: Some(self.size),
+                },
+                wgpu::Extent3d {
+                    width: self.size,
+                    height: self.size,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        let view = tex.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("skybox_tex"),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        (tex, view)
+    }
+}
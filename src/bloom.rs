@@ -0,0 +1,221 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::gpu::storage_wgsl;
+use crate::texture::create_storage_texture;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct BloomUbo {
+    // Luminance above which pixels start to glow.
+    pub(crate) threshold: f32,
+    // Strength of the blurred glow added back in the blit.
+    pub(crate) intensity: f32,
+    pub(crate) enabled: u32,
+    _pad: u32,
+}
+
+/// Bright pass plus separable blur over the trace output, at half
+/// resolution. The result stays in `textures[0]` for the blit to add.
+pub(crate) struct BloomPass {
+    pub(crate) params: BloomUbo,
+    pub(crate) buf: wgpu::Buffer,
+    bgl: wgpu::BindGroupLayout,
+    // Same as the trace's storage format; bloom.wgsl is rewritten to match.
+    format: wgpu::TextureFormat,
+    textures: [(wgpu::Texture, wgpu::TextureView); 2],
+    // The bright pass from either of the trace's ping-ponged outputs, then
+    // the horizontal and vertical blurs.
+    bright_bind_groups: [wgpu::BindGroup; 2],
+    blur_bind_groups: [wgpu::BindGroup; 2],
+    pipelines: [wgpu::ComputePipeline; 3],
+    // The glow no longer matches the trace, e.g. after toggling it back on.
+    pub(crate) stale: bool,
+}
+
+impl BloomPass {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        storage_views: [&wgpu::TextureView; 2],
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        threshold: f32,
+        intensity: f32,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bloom.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(storage_wgsl(include_str!("../shaders/bloom.wgsl"), format)),
+        });
+        let params = BloomUbo { threshold, intensity, enabled: 1, _pad: 0 };
+        let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("bloom_ubo"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom_bgl"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pl = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bloom_pl"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+        let pipelines = ["bright_pass", "blur_h", "blur_v"].map(|entry| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry),
+                layout: Some(&pl),
+                module: &module,
+                entry_point: Some(entry),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        });
+        let textures = create_bloom_textures(device, width, height, format);
+        let (bright_bind_groups, blur_bind_groups) =
+            create_bloom_bind_groups(device, &bgl, &buf, storage_views, &textures);
+
+        Self {
+            params,
+            buf,
+            bgl,
+            format,
+            textures,
+            bright_bind_groups,
+            blur_bind_groups,
+            pipelines,
+            stale: true,
+        }
+    }
+
+    /// The blurred glow, for sampling in the blit.
+    pub(crate) fn view(&self) -> &wgpu::TextureView {
+        &self.textures[0].1
+    }
+
+    /// Rebuilds the half-res targets for a trace of `width`x`height`.
+    pub(crate) fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        storage_views: [&wgpu::TextureView; 2],
+        width: u32,
+        height: u32,
+    ) {
+        self.textures = create_bloom_textures(device, width, height, self.format);
+        (self.bright_bind_groups, self.blur_bind_groups) =
+            create_bloom_bind_groups(device, &self.bgl, &self.buf, storage_views, &self.textures);
+        self.stale = true;
+    }
+
+    pub(crate) fn toggle(&mut self, queue: &wgpu::Queue) {
+        self.params.enabled ^= 1;
+        self.stale = true;
+        queue.write_buffer(&self.buf, 0, bytemuck::bytes_of(&self.params));
+        log::info!("bloom: {}", if self.params.enabled != 0 { "on" } else { "off" });
+    }
+
+    /// Records the three bloom dispatches over the trace output `source`
+    /// (0 or 1). Skipped entirely while disabled.
+    pub(crate) fn encode(&mut self, encoder: &mut wgpu::CommandEncoder, source: usize) {
+        if self.params.enabled == 0 {
+            return;
+        }
+        let width = self.textures[0].0.width();
+        let height = self.textures[0].0.height();
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("bloom_compute"),
+            timestamp_writes: None,
+        });
+        let [blur_h, blur_v] = &self.blur_bind_groups;
+        let bind_groups = [&self.bright_bind_groups[source], blur_h, blur_v];
+        for (pipeline, bind_group) in self.pipelines.iter().zip(bind_groups) {
+            cpass.set_pipeline(pipeline);
+            cpass.set_bind_group(0, bind_group, &[]);
+            cpass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+        }
+        self.stale = false;
+    }
+}
+
+fn create_bloom_textures(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> [(wgpu::Texture, wgpu::TextureView); 2] {
+    let (width, height) = ((width / 2).max(1), (height / 2).max(1));
+    ["bloom_tex_0", "bloom_tex_1"]
+        .map(|label| create_storage_texture(device, label, width, height, format))
+}
+
+fn create_bloom_bind_groups(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buf: &wgpu::Buffer,
+    storage_views: [&wgpu::TextureView; 2],
+    textures: &[(wgpu::Texture, wgpu::TextureView); 2],
+) -> ([wgpu::BindGroup; 2], [wgpu::BindGroup; 2]) {
+    let bind_group = |label, src, dst| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(src),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(dst),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buf.as_entire_binding(),
+                },
+            ],
+        })
+    };
+    // (source, destination) per pass: trace -> 0, 0 -> 1, 1 -> 0
+    let [storage_0, storage_1] = storage_views;
+    let bright = [
+        bind_group("bloom_bright_bg_0", storage_0, &textures[0].1),
+        bind_group("bloom_bright_bg_1", storage_1, &textures[0].1),
+    ];
+    let blur = [
+        bind_group("bloom_blur_h_bg", &textures[0].1, &textures[1].1),
+        bind_group("bloom_blur_v_bg", &textures[1].1, &textures[0].1),
+    ];
+    (bright, blur)
+}
@@ -0,0 +1,669 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+use winit::event::TouchPhase;
+use winit::keyboard::KeyCode;
+
+use crate::config::Config;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct CameraUbo {
+    pub(crate) view_inv: [[f32; 4]; 4],
+    pub(crate) proj_inv: [[f32; 4]; 4],
+    pub(crate) params: [f32; 3], // (width, height, time)
+    pub(crate) _pad0: f32,
+}
+
+impl CameraUbo {
+    /// World to clip space for this upload's view.
+    pub(crate) fn view_proj(&self) -> Mat4 {
+        Mat4::from_cols_array_2d(&self.proj_inv).inverse() * Mat4::from_cols_array_2d(&self.view_inv).inverse()
+    }
+
+    /// Whether two uploads trace the same image, ignoring `time`.
+    pub(crate) fn same_view(&self, other: &Self) -> bool {
+        self.view_inv == other.view_inv
+            && self.proj_inv == other.proj_inv
+            && self.params[..2] == other.params[..2]
+    }
+}
+
+// Fly speed in orbit radii per second, so movement feels the same at any zoom.
+const FLY_SPEED: f32 = 0.75;
+const FLY_BOOST: f32 = 4.0;
+pub const DEFAULT_FOV_Y_DEG: f32 = 60.0;
+const FOV_RANGE_DEG: (f32, f32) = (10.0, 120.0);
+pub const FOV_STEP_DEG: f32 = 5.0;
+// Pan distance per pixel of cursor motion, also in orbit radii.
+const PAN_SENSITIVITY: f32 = 0.0015;
+// Time constant (seconds) for orbit/zoom smoothing; 0 snaps immediately.
+const CAMERA_DAMPING: f32 = 0.08;
+// Drag rotation in radians per pixel, and the radius change per scroll line.
+const DEFAULT_ORBIT_SENSITIVITY: f32 = 0.005;
+const DEFAULT_ZOOM_SENSITIVITY: f32 = 0.1;
+// Orbit radius limits for zooming and refocusing.
+const RADIUS_RANGE: (f32, f32) = (1.0, 50.0);
+// The up vector turns with the orbit, so there is no pole to stop short
+// of; a limit of half a turn or more leaves pitch free to go over the top.
+pub(crate) const DEFAULT_PITCH_LIMIT_DEG: f32 = 180.0;
+// Depth of field: lens radius limits and steps, in scene units.
+const APERTURE_MAX: f32 = 0.5;
+const APERTURE_STEP: f32 = 0.02;
+const FOCUS_RANGE: (f32, f32) = (0.5, 100.0);
+const FOCUS_STEP: f32 = 1.1;
+// Projection clip planes; the near plane is also where rays start.
+pub(crate) const DEFAULT_NEAR: f32 = 0.1;
+pub(crate) const DEFAULT_FAR: f32 = 1000.0;
+// Width over height the projection is built for at most, or at least its
+// inverse; a sliver of a window is stretched rather than projected with a
+// near-singular matrix.
+const ASPECT_MAX: f32 = 32.0;
+// Width-over-height ratios the letterbox cycles through; `None` follows the
+// window.
+const ASPECT_LOCKS: [Option<f32>; 5] = [None, Some(2.39), Some(1.85), Some(16.0 / 9.0), Some(4.0 / 3.0)];
+// Orbit momentum after a drag is released: the velocity decays by
+// e^-friction per second, so the default all but stops it dead. Only drag
+// motion from the last `THROW_WINDOW` counts, so pausing before letting go
+// doesn't throw, and spins slower than `THROW_MIN_SPEED` (radians per
+// second) stop.
+pub(crate) const DEFAULT_ORBIT_FRICTION: f32 = 50.0;
+const THROW_WINDOW: Duration = Duration::from_millis(80);
+const THROW_MIN_SPEED: f32 = 0.01;
+// Yaw rate of the hands-off orbit, in radians per second.
+const DEFAULT_ORBIT_SPEED: f32 = 0.3;
+
+// Canonical views on keys 1-6: (name, yaw, pitch in degrees, radius). The
+// disk lies in the y = 0 plane, so pitch 0 is edge-on and 90 face-on.
+const PRESETS: [(&str, f32, f32, f32); 6] = [
+    ("front", 90.0, 10.0, 4.0),
+    ("side", 0.0, 10.0, 4.0),
+    ("top", 0.0, 90.0, 4.0),
+    ("three-quarter", 45.0, 30.0, 5.0),
+    ("edge-on disk", 90.0, 0.0, 9.0),
+    ("face-on disk", 90.0, 90.0, 9.0),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Projection {
+    Perspective,
+    // Parallel rays; the view covers what the perspective one shows at the
+    // target's distance.
+    Orthographic,
+}
+
+/// The persistent part of `CameraCtrl`, as written to `camera.json`.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct CameraPose {
+    #[serde(default)]
+    target: [f32; 3],
+    yaw: f32,
+    pitch: f32,
+    radius: f32,
+    fov_y: f32,
+}
+
+pub struct CameraCtrl {
+    pub target: Vec3,
+    // Actual orbit values used for rendering; they glide toward the
+    // `target_*` values that input writes to.
+    pub yaw: f32,
+    pub pitch: f32,
+    pub radius: f32,
+    pub target_yaw: f32,
+    pub target_pitch: f32,
+    pub target_radius: f32,
+    pub damping: f32,
+    pub fov_y: f32,
+    pub dragging: bool,
+    pub panning: bool,
+    pub last_cursor: Option<Vec2>,
+    pub held_keys: HashSet<KeyCode>,
+    pub boost: bool,
+    // Pose that `reset` glides back to.
+    pub home: CameraPose,
+    pub projection: Projection,
+    // Aspect ratio the image is framed at whatever the window's shape; the
+    // rest of the window is letterboxed.
+    pub aspect_lock: Option<f32>,
+    // Hands-off rotation about the target for demos, toggled with T.
+    pub auto_orbit: bool,
+    pub orbit_speed: f32,
+    pub orbit_sensitivity: f32,
+    pub zoom_sensitivity: f32,
+    // Drag up to look up instead of to move the camera up.
+    pub invert_y: bool,
+    // Largest |pitch| in radians; `PI` or more is no limit.
+    pub pitch_limit: f32,
+    // Recent drag steps in (yaw, pitch) radians, and the spin they leave
+    // once the button is released, in radians per second.
+    drag_history: VecDeque<(Instant, Vec2)>,
+    pub orbit_velocity: Vec2,
+    pub orbit_friction: f32,
+    pub aperture: f32,
+    pub focus_distance: f32,
+    pub near: f32,
+    pub far: f32,
+    // Fingers currently on a touchscreen, by id, at their last position.
+    pub touches: HashMap<u64, Vec2>,
+}
+
+impl Default for CameraCtrl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraCtrl {
+    pub fn new() -> Self {
+        Self {
+            target: Vec3::ZERO,
+            yaw: 0.6,
+            pitch: 0.3,
+            radius: 4.0,
+            target_yaw: 0.6,
+            target_pitch: 0.3,
+            target_radius: 4.0,
+            damping: CAMERA_DAMPING,
+            fov_y: DEFAULT_FOV_Y_DEG.to_radians(),
+            dragging: false,
+            panning: false,
+            last_cursor: None,
+            held_keys: HashSet::new(),
+            boost: false,
+            home: CameraPose {
+                target: [0.0; 3],
+                yaw: 0.6,
+                pitch: 0.3,
+                radius: 4.0,
+                fov_y: DEFAULT_FOV_Y_DEG.to_radians(),
+            },
+            projection: Projection::Perspective,
+            aspect_lock: None,
+            auto_orbit: false,
+            orbit_speed: DEFAULT_ORBIT_SPEED,
+            orbit_sensitivity: DEFAULT_ORBIT_SENSITIVITY,
+            zoom_sensitivity: DEFAULT_ZOOM_SENSITIVITY,
+            invert_y: false,
+            pitch_limit: DEFAULT_PITCH_LIMIT_DEG.to_radians(),
+            drag_history: VecDeque::new(),
+            orbit_velocity: Vec2::ZERO,
+            orbit_friction: DEFAULT_ORBIT_FRICTION,
+            aperture: 0.0,
+            focus_distance: 4.0,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+            touches: HashMap::new(),
+        }
+    }
+
+    /// Starts at the pose from `config` instead of the built-in one.
+    pub fn from_config(config: &Config) -> Self {
+        let (min_fov, max_fov) = FOV_RANGE_DEG;
+        let (min_radius, max_radius) = RADIUS_RANGE;
+        let mut ctrl = Self::new();
+        ctrl.orbit_sensitivity = config.orbit_sensitivity.max(0.0);
+        ctrl.zoom_sensitivity = config.zoom_sensitivity.clamp(0.0, 0.5);
+        ctrl.invert_y = config.invert_y;
+        ctrl.pitch_limit = config.pitch_limit.clamp(0.0, DEFAULT_PITCH_LIMIT_DEG).to_radians();
+        ctrl.orbit_friction = config.orbit_friction.max(0.0);
+        ctrl.yaw = config.yaw.to_radians();
+        ctrl.pitch = ctrl.clamp_pitch(config.pitch.to_radians());
+        ctrl.radius = config.radius.clamp(min_radius, max_radius);
+        ctrl.target_yaw = ctrl.yaw;
+        ctrl.target_pitch = ctrl.pitch;
+        ctrl.target_radius = ctrl.radius;
+        ctrl.fov_y = config.fov_y.clamp(min_fov, max_fov).to_radians();
+        ctrl.set_clip_planes(config.near, config.far);
+        ctrl.aspect_lock = config.aspect_lock.map(|aspect| aspect.clamp(1.0 / ASPECT_MAX, ASPECT_MAX));
+        ctrl.home = ctrl.pose();
+        ctrl
+    }
+    /// Sets the clip planes, keeping `near` positive and `far` beyond it.
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        self.near = near.max(1e-4);
+        self.far = far.max(self.near * 2.0);
+        if far <= near {
+            log::warn!("far plane must lie beyond the near plane; using {}", self.far);
+        }
+    }
+
+    /// Rotation taking +X to the eye's direction from the target and +Y to
+    /// the view's up: pitch tilts +X up toward +Y, then yaw turns it about
+    /// the world axis. Up turns with the eye, so looking straight down
+    /// (the world up and the view direction parallel) is not a special case
+    /// and past the pole the view simply continues upside down.
+    fn orientation(&self) -> Quat {
+        Quat::from_rotation_y(-self.yaw) * Quat::from_rotation_z(self.pitch)
+    }
+
+    fn eye_target_up(&self) -> (Vec3, Vec3, Vec3) {
+        let orientation = self.orientation();
+        let eye = self.target + orientation * (self.radius * Vec3::X);
+        (eye, self.target, orientation * Vec3::Y)
+    }
+
+    /// `pitch` held within `pitch_limit`, unless that is half a turn or more.
+    fn clamp_pitch(&self, pitch: f32) -> f32 {
+        if self.pitch_limit >= PI { pitch } else { pitch.clamp(-self.pitch_limit, self.pitch_limit) }
+    }
+
+    /// Whether the orbit has carried the camera over a pole, so the view
+    /// is upside down and left/right drags have to turn the other way.
+    fn upside_down(&self) -> bool {
+        self.target_pitch.cos() < 0.0
+    }
+
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            target: self.target.to_array(),
+            yaw: self.yaw,
+            pitch: self.pitch,
+            radius: self.radius,
+            fov_y: self.fov_y,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, &self.pose())?;
+        Ok(())
+    }
+
+    /// Replaces the pose with the one saved at `path`, keeping input state
+    /// and settings.
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        let file = std::fs::File::open(path)?;
+        let pose: CameraPose = serde_json::from_reader(io::BufReader::new(file))?;
+        self.set_pose(pose);
+        Ok(())
+    }
+
+    /// Jumps to `pose` without gliding, held within the same limits as a
+    /// pose from the config since saved files can be hand-edited.
+    pub fn set_pose(&mut self, pose: CameraPose) {
+        let (min_radius, max_radius) = RADIUS_RANGE;
+        let (min_fov, max_fov) = FOV_RANGE_DEG;
+        self.target = Vec3::from_array(pose.target);
+        self.yaw = pose.yaw;
+        self.pitch = self.clamp_pitch(pose.pitch);
+        self.radius = pose.radius.clamp(min_radius, max_radius);
+        self.target_yaw = self.yaw;
+        self.target_pitch = self.pitch;
+        self.target_radius = self.radius;
+        self.fov_y = pose.fov_y.clamp(min_fov.to_radians(), max_fov.to_radians());
+    }
+
+    /// Turns a cursor drag of `delta` pixels into orbit motion.
+    pub fn orbit(&mut self, delta: Vec2) {
+        let dy = if self.invert_y { -delta.y } else { delta.y };
+        let dx = if self.upside_down() { -delta.x } else { delta.x };
+        let step = Vec2::new(-dx, -dy) * self.orbit_sensitivity;
+        self.target_yaw += step.x;
+        self.target_pitch = self.clamp_pitch(self.target_pitch + step.y);
+        let now = Instant::now();
+        self.drag_history.push_back((now, step));
+        while self.drag_history.front().is_some_and(|&(at, _)| now - at > THROW_WINDOW) {
+            self.drag_history.pop_front();
+        }
+    }
+
+    /// Starts or ends a mouse orbit drag. Grabbing stops any spin left from
+    /// the last one; letting go throws the camera with the drag's recent
+    /// speed.
+    pub fn set_dragging(&mut self, dragging: bool) {
+        self.dragging = dragging;
+        self.last_cursor = None;
+        self.orbit_velocity = Vec2::ZERO;
+        if !dragging {
+            let now = Instant::now();
+            let recent = self.drag_history.iter().filter(|&&(at, _)| now - at <= THROW_WINDOW);
+            let (oldest, total) = recent.fold((now, Vec2::ZERO), |(oldest, sum), &(at, step)| {
+                (oldest.min(at), sum + step)
+            });
+            let span = (now - oldest).max(Duration::from_millis(1)).as_secs_f32();
+            self.orbit_velocity = total / span;
+        }
+        self.drag_history.clear();
+    }
+
+    /// Zooms by `scroll` wheel lines; positive moves closer.
+    pub fn zoom(&mut self, scroll: f32) {
+        self.scale_radius(1.0 - scroll * self.zoom_sensitivity);
+    }
+
+    /// Zooms like `zoom`, but toward the point under `cursor` (in pixels on
+    /// a `screen`-sized surface) rather than the orbit center. The cursor's
+    /// ray is taken to the focal sphere, one orbit radius from the eye, and
+    /// the target slides toward that point by the fraction the radius
+    /// shrinks, which keeps it under the cursor.
+    pub fn zoom_at(&mut self, scroll: f32, cursor: Vec2, screen: (u32, u32)) {
+        let before = self.target_radius;
+        self.zoom(scroll);
+        let shrink = 1.0 - self.target_radius / before;
+        if shrink == 0.0 || screen.0 == 0 || screen.1 == 0 {
+            return;
+        }
+        let (origin, dir) = self.cursor_ray(cursor, screen);
+        let point = match self.projection {
+            Projection::Perspective => origin + dir * self.radius,
+            // Parallel rays: use the view plane through the target instead.
+            Projection::Orthographic => {
+                let (forward, _, _) = self.basis();
+                origin + dir * (self.target - origin).dot(forward) / dir.dot(forward)
+            }
+        };
+        self.target += (point - self.target) * shrink;
+    }
+
+    /// World-space ray through `cursor` as (origin, unit direction); the
+    /// CPU mirror of `world_ray_at` in trace.wgsl, without the lens. The
+    /// origin is the eye for the perspective projection.
+    fn cursor_ray(&self, cursor: Vec2, (width, height): (u32, u32)) -> (Vec3, Vec3) {
+        let (view_inv, proj_inv) = compute_camera_mats(self, width, height);
+        let [x, y, w, h] = active_region(self.aspect_lock, width, height).map(|c| c as f32);
+        let uv = (cursor - Vec2::new(x, y)) / Vec2::new(w, h);
+        let ndc = Vec2::new(2.0 * uv.x - 1.0, 1.0 - 2.0 * uv.y);
+        let unproject = |depth: f32| {
+            let p = proj_inv * Vec4::new(ndc.x, ndc.y, depth, 1.0);
+            view_inv.transform_point3(p.truncate() / p.w)
+        };
+        let (near, far) = (unproject(0.0), unproject(1.0));
+        let dir = (far - near).normalize();
+        match self.projection {
+            Projection::Perspective => (self.eye_target_up().0, dir),
+            Projection::Orthographic => (near, dir),
+        }
+    }
+
+    fn scale_radius(&mut self, factor: f32) {
+        let (min, max) = RADIUS_RANGE;
+        self.target_radius = (self.target_radius * factor.clamp(0.2, 5.0)).clamp(min, max);
+    }
+
+    /// One finger orbits like a mouse drag; two pinch to zoom, the radius
+    /// following the change in distance between them. Returns whether the
+    /// camera moved.
+    pub fn touch(&mut self, phase: TouchPhase, id: u64, pos: Vec2) -> bool {
+        match phase {
+            TouchPhase::Started => {
+                self.touches.insert(id, pos);
+                false
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&id);
+                false
+            }
+            TouchPhase::Moved => {
+                let Some(prev) = self.touches.insert(id, pos) else {
+                    return false;
+                };
+                match self.touches.len() {
+                    1 => self.orbit(pos - prev),
+                    2 => {
+                        let other = self
+                            .touches
+                            .iter()
+                            .find_map(|(&other_id, &p)| (other_id != id).then_some(p))
+                            .expect("two touches");
+                        let before = prev.distance(other);
+                        let after = pos.distance(other);
+                        if before <= 1.0 || after <= 1.0 {
+                            return false;
+                        }
+                        // Spreading the fingers apart moves closer.
+                        self.scale_radius(before / after);
+                    }
+                    // Three or more fingers aren't a gesture we handle.
+                    _ => return false,
+                }
+                true
+            }
+        }
+    }
+
+    /// Sets yaw immediately, bypassing damping.
+    pub fn snap_yaw(&mut self, yaw: f32) {
+        self.yaw = yaw;
+        self.target_yaw = yaw;
+    }
+
+    /// Glides back to the startup pose and drops any in-progress drag so the
+    /// next cursor move doesn't jump from a stale anchor.
+    pub fn reset(&mut self) {
+        let home = self.home;
+        self.target = Vec3::from_array(home.target);
+        self.target_yaw = home.yaw;
+        self.target_pitch = home.pitch;
+        self.target_radius = home.radius;
+        self.fov_y = home.fov_y;
+        self.dragging = false;
+        self.panning = false;
+        self.last_cursor = None;
+        self.orbit_velocity = Vec2::ZERO;
+    }
+
+    /// Glides to preset `index` of `PRESETS`, turning the short way round.
+    pub fn go_to_preset(&mut self, index: usize) {
+        let Some(&(name, yaw, pitch, radius)) = PRESETS.get(index) else {
+            return;
+        };
+        self.orbit_velocity = Vec2::ZERO;
+        let turn = (yaw.to_radians() - self.target_yaw + PI).rem_euclid(2.0 * PI) - PI;
+        self.target_yaw += turn;
+        let tilt = (pitch.to_radians() - self.target_pitch + PI).rem_euclid(2.0 * PI) - PI;
+        self.target_pitch = self.clamp_pitch(self.target_pitch + tilt);
+        self.target_radius = radius;
+        log::info!("camera preset {}: {name}", index + 1);
+    }
+
+    /// Orbits around `point` from here on. The eye stays put (up to the
+    /// radius limits) and the view turns at once to center the point.
+    pub fn focus_on(&mut self, point: Vec3) {
+        let (eye, ..) = self.eye_target_up();
+        let offset = eye - point;
+        let distance = offset.length();
+        if distance < 1e-4 {
+            return;
+        }
+        let (min, max) = RADIUS_RANGE;
+        // Stay on the same side of the pole, and unwrap the new angles next
+        // to the old ones so damping doesn't spin the long way round on the
+        // next orbit.
+        let upside_down = self.pitch.cos() < 0.0;
+        let flat = if upside_down { -offset } else { offset };
+        let yaw = flat.z.atan2(flat.x);
+        let elevation = (offset.y / distance).asin();
+        let pitch = if upside_down { PI - elevation } else { elevation };
+        self.yaw += (yaw - self.yaw + PI).rem_euclid(2.0 * PI) - PI;
+        self.pitch = self.clamp_pitch(self.pitch + (pitch - self.pitch + PI).rem_euclid(2.0 * PI) - PI);
+        self.radius = distance.clamp(min, max);
+        self.target = point;
+        self.target_yaw = self.yaw;
+        self.target_pitch = self.pitch;
+        self.target_radius = self.radius;
+        log::info!("orbiting ({:.2}, {:.2}, {:.2})", point.x, point.y, point.z);
+    }
+
+    pub fn adjust_fov(&mut self, delta_deg: f32) {
+        let (min, max) = FOV_RANGE_DEG;
+        self.fov_y = (self.fov_y.to_degrees() + delta_deg).clamp(min, max).to_radians();
+    }
+
+    pub fn toggle_projection(&mut self) {
+        self.projection = match self.projection {
+            Projection::Perspective => Projection::Orthographic,
+            Projection::Orthographic => Projection::Perspective,
+        };
+        log::info!("projection: {:?}", self.projection);
+    }
+
+    /// Steps to the next of `ASPECT_LOCKS`.
+    pub fn cycle_aspect_lock(&mut self) {
+        let next = ASPECT_LOCKS
+            .iter()
+            .position(|&lock| lock == self.aspect_lock)
+            .map_or(0, |i| (i + 1) % ASPECT_LOCKS.len());
+        self.aspect_lock = ASPECT_LOCKS[next];
+        match self.aspect_lock {
+            Some(aspect) => log::info!("aspect lock: {aspect:.2}:1"),
+            None => log::info!("aspect lock: off"),
+        }
+    }
+
+    pub fn toggle_auto_orbit(&mut self) {
+        self.auto_orbit = !self.auto_orbit;
+        log::info!("auto-orbit: {}", if self.auto_orbit { "on" } else { "off" });
+    }
+
+    /// Widens (positive `steps`) or narrows the lens; 0 is a pinhole.
+    pub fn step_aperture(&mut self, steps: f32) {
+        self.aperture = (self.aperture + steps * APERTURE_STEP).clamp(0.0, APERTURE_MAX);
+        log::info!("aperture: {:.2}", self.aperture);
+    }
+
+    /// Moves the focal plane `steps` multiplicative steps farther away.
+    pub fn step_focus(&mut self, steps: i32) {
+        let (min, max) = FOCUS_RANGE;
+        self.focus_distance = (self.focus_distance * FOCUS_STEP.powi(steps)).clamp(min, max);
+        log::info!("focus distance: {:.2}", self.focus_distance);
+    }
+
+    /// Orthonormal camera basis as (forward, right, up).
+    fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let (eye, target, up) = self.eye_target_up();
+        let forward = (target - eye).normalize();
+        let right = forward.cross(up).normalize();
+        (forward, right, right.cross(forward))
+    }
+
+    /// Slides the look-at target in the view plane so the scene follows the cursor.
+    pub fn pan(&mut self, delta: Vec2) {
+        let (_, right, up) = self.basis();
+        let scale = PAN_SENSITIVITY * self.radius;
+        self.target += (-right * delta.x + up * delta.y) * scale;
+    }
+
+    /// Tracks which fly-through keys (W/A/S/D/Q/E) are currently held.
+    pub fn set_key(&mut self, code: KeyCode, pressed: bool) {
+        if !matches!(
+            code,
+            KeyCode::KeyW | KeyCode::KeyA | KeyCode::KeyS | KeyCode::KeyD | KeyCode::KeyQ | KeyCode::KeyE
+        ) {
+            return;
+        }
+        if pressed {
+            self.held_keys.insert(code);
+        } else {
+            self.held_keys.remove(&code);
+        }
+    }
+
+    fn is_settling(&self) -> bool {
+        const EPS: f32 = 1e-4;
+        (self.target_yaw - self.yaw).abs() > EPS
+            || (self.target_pitch - self.pitch).abs() > EPS
+            || (self.target_radius - self.radius).abs() > EPS * self.target_radius
+    }
+
+    /// Whether the camera will change on its own over the next frames.
+    pub fn is_moving(&self) -> bool {
+        self.is_settling()
+            || self.auto_orbit
+            || self.orbit_velocity != Vec2::ZERO
+            || !self.held_keys.is_empty()
+    }
+
+    /// Advances damping and continuous, key-driven motion by `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        if self.auto_orbit {
+            // Move the target too, so damping doesn't pull against the orbit.
+            self.target_yaw += self.orbit_speed * dt;
+            self.yaw += self.orbit_speed * dt;
+            if self.yaw > 2.0 * PI {
+                // Wrap both together so long demos don't lose precision.
+                self.yaw -= 2.0 * PI;
+                self.target_yaw -= 2.0 * PI;
+            }
+        }
+        if self.orbit_velocity != Vec2::ZERO {
+            self.target_yaw += self.orbit_velocity.x * dt;
+            self.target_pitch = self.clamp_pitch(self.target_pitch + self.orbit_velocity.y * dt);
+            self.orbit_velocity *= (-self.orbit_friction * dt).exp();
+            if self.orbit_velocity.length() < THROW_MIN_SPEED {
+                self.orbit_velocity = Vec2::ZERO;
+            }
+        }
+        // Frame-rate independent exponential approach toward the targets.
+        let t = if self.damping > 0.0 { 1.0 - (-dt / self.damping).exp() } else { 1.0 };
+        self.yaw += (self.target_yaw - self.yaw) * t;
+        self.pitch += (self.target_pitch - self.pitch) * t;
+        self.radius += (self.target_radius - self.radius) * t;
+        if !self.is_settling() {
+            // Land exactly on the target so the view stops changing.
+            self.yaw = self.target_yaw;
+            self.pitch = self.target_pitch;
+            self.radius = self.target_radius;
+        }
+
+        if self.held_keys.is_empty() {
+            return;
+        }
+        let (forward, right, up) = self.basis();
+        let mut dir = Vec3::ZERO;
+        for key in &self.held_keys {
+            dir += match key {
+                KeyCode::KeyW => forward,
+                KeyCode::KeyS => -forward,
+                KeyCode::KeyD => right,
+                KeyCode::KeyA => -right,
+                KeyCode::KeyE => up,
+                KeyCode::KeyQ => -up,
+                _ => Vec3::ZERO,
+            };
+        }
+        let speed = FLY_SPEED * self.radius * if self.boost { FLY_BOOST } else { 1.0 };
+        self.target += dir.normalize_or_zero() * speed * dt;
+    }
+}
+
+/// The part of a `width`x`height` output the image covers, as (x, y, width,
+/// height) in pixels: all of it, or with `aspect_lock` the largest centered
+/// rectangle of that aspect, leaving bars on two sides.
+pub(crate) fn active_region(aspect_lock: Option<f32>, width: u32, height: u32) -> [u32; 4] {
+    let Some(aspect) = aspect_lock else {
+        return [0, 0, width, height];
+    };
+    let w = ((height as f32 * aspect).round() as u32).clamp(1, width.max(1));
+    let h = ((width as f32 / aspect).round() as u32).clamp(1, height.max(1));
+    [(width.saturating_sub(w)) / 2, (height.saturating_sub(h)) / 2, w, h]
+}
+
+/// `width`x`height` is the surface size in physical pixels; only its ratio
+/// is used, so DPI scaling and the trace resolution don't affect the aspect.
+/// An aspect lock overrides it.
+pub(crate) fn compute_camera_mats(ctrl: &CameraCtrl, width: u32, height: u32) -> (Mat4, Mat4) {
+    let (eye, target, up) = ctrl.eye_target_up();
+    let view = Mat4::look_at_rh(eye, target, up);
+    let view_inv = view.inverse();
+
+    let window_aspect = (width.max(1) as f32) / (height.max(1) as f32);
+    let aspect = ctrl.aspect_lock.unwrap_or(window_aspect).clamp(1.0 / ASPECT_MAX, ASPECT_MAX);
+    let proj = match ctrl.projection {
+        Projection::Perspective => Mat4::perspective_rh(ctrl.fov_y, aspect, ctrl.near, ctrl.far),
+        Projection::Orthographic => {
+            let half_h = ctrl.radius * (0.5 * ctrl.fov_y).tan();
+            let half_w = half_h * aspect;
+            Mat4::orthographic_rh(-half_w, half_w, -half_h, half_h, ctrl.near, ctrl.far)
+        }
+    };
+    let proj_inv = proj.inverse();
+    (view_inv, proj_inv)
+}
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Present mode requested in `config.toml`; `Auto` prefers Mailbox and
+/// falls back to Fifo.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresentMode {
+    Auto,
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+/// Texel format of the trace output and accumulation, as `storage_format`
+/// in `config.toml` or `--storage-format`, for checking whether banding
+/// comes from storage precision.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageFormat {
+    Rgba8Unorm,
+    Rgba16Float,
+    Rgba32Float,
+}
+
+impl std::str::FromStr for StorageFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "rgba8unorm" => Ok(Self::Rgba8Unorm),
+            "rgba16float" => Ok(Self::Rgba16Float),
+            "rgba32float" => Ok(Self::Rgba32Float),
+            _ => Err(format!("unknown storage format {name:?}; expected rgba8unorm, rgba16float or rgba32float")),
+        }
+    }
+}
+
+/// What escaped rays show, as `sky` in `config.toml`: `"black"`,
+/// `"stars"`, `"skybox"` or `{ color = [r, g, b] }` in linear HDR units.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sky {
+    Black,
+    Color([f32; 3]),
+    Stars,
+    Skybox,
+}
+
+/// How the disk is colored, as `disk_emission` in `config.toml`: the
+/// `"ramp"` texture, or a `"blackbody"` spectrum at the local temperature.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiskEmission {
+    Ramp,
+    Blackbody,
+}
+
+/// An extra black hole, as a `[[holes]]` table in `config.toml`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HoleConfig {
+    pub position: [f32; 3],
+    pub mass: f32,
+}
+
+/// Startup parameters read from `config.toml`. Every key is optional and
+/// missing ones keep the built-in defaults. Angles are in degrees.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub fov_y: f32,
+    pub radius: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    // Mouse controls: drag radians per pixel, zoom fraction per scroll line,
+    // drag-up-to-look-up, and the largest pitch in degrees.
+    pub orbit_sensitivity: f32,
+    pub zoom_sensitivity: f32,
+    pub invert_y: bool,
+    pub pitch_limit: f32,
+    // How fast the spin left after a released drag dies away, per second.
+    pub orbit_friction: f32,
+    // Width over height to frame the image at, letterboxing the window to
+    // fit; the window's own aspect when unset.
+    pub aspect_lock: Option<f32>,
+    // Projection clip planes in scene units.
+    pub near: f32,
+    pub far: f32,
+    // Logical window size; the platform default is used when unset.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub present_mode: PresentMode,
+    // Graphics APIs to look for adapters on, as a comma list like "vulkan"
+    // or "gl"; every one wgpu supports when unset.
+    pub backend: Option<String>,
+    // Only adapters whose name contains this, ignoring case.
+    pub adapter_name: Option<String>,
+    // Trace output format; rgba16float, or rgba8unorm where that can't be
+    // written from a shader, when unset.
+    pub storage_format: Option<StorageFormat>,
+    // Render-rate cap in either present mode; --max-fps overrides it.
+    pub max_fps: Option<u32>,
+    pub resolution_scale: f32,
+    // Frame time in ms that adaptive resolution holds; off when unset.
+    pub target_frame_ms: Option<f32>,
+    // Geodesic integration budget per ray, and step length relative to the
+    // distance from the horizon.
+    pub max_steps: u32,
+    pub step_size: f32,
+    // Pixels over which the shadow's edge is anti-aliased in each frame.
+    pub shadow_softness: f32,
+    // Pixels the photon ring is box-filtered over in each frame; 0 is off.
+    pub ring_filter_width: f32,
+    // Trace one ray per logical rather than physical pixel on HiDPI screens.
+    pub trace_logical_resolution: bool,
+    // Alternate trace shader to experiment with, its compute entry point,
+    // and values for its `override` constants by name; trace.wgsl and
+    // `main` when unset.
+    pub trace_shader: Option<PathBuf>,
+    pub trace_entry_point: Option<String>,
+    pub trace_constants: BTreeMap<String, f64>,
+    // Power-law display gamma for surfaces without an sRGB format; those
+    // get the exact sRGB curve when unset.
+    pub gamma: Option<f32>,
+    // Blit sharpening of the upscaled trace, 0 (off) to 1.
+    pub sharpen: f32,
+    // Lens effects in the blit, off unless enabled: corner darkening from 0
+    // to 1, and color fringing toward the edges.
+    pub lens_effects: bool,
+    pub vignette_strength: f32,
+    pub aberration_strength: f32,
+    // Keep tracing in the background, e.g. while screen recording.
+    pub render_when_unfocused: bool,
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    // Equirectangular PNG or HDR sky; procedural stars when unset.
+    pub background: Option<PathBuf>,
+    // Folder of px/nx/py/ny/pz/nz.png cube faces; replaces the background.
+    pub skybox: Option<PathBuf>,
+    // The skybox if one is set, otherwise the stars, when unset.
+    pub sky: Option<Sky>,
+    // PNG gradient strip for the disk colors, inner edge on the left.
+    pub disk_ramp: Option<PathBuf>,
+    // Ramp or blackbody disk colors, and the blackbody's temperature in
+    // kelvin at the inner and outer edge.
+    pub disk_emission: DiskEmission,
+    pub disk_temp_inner: f32,
+    pub disk_temp_outer: f32,
+    // Sampling seed for reproducible renders; time-based when unset.
+    pub seed: Option<u32>,
+    // Extra black holes besides the one at the origin.
+    pub holes: Vec<HoleConfig>,
+    // Whether the file sets any of the camera pose keys, which then win
+    // over the last run's camera.
+    #[serde(skip)]
+    pub pose_given: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fov_y: crate::camera::DEFAULT_FOV_Y_DEG,
+            radius: 4.0,
+            yaw: 0.6_f32.to_degrees(),
+            pitch: 0.3_f32.to_degrees(),
+            orbit_sensitivity: 0.005,
+            zoom_sensitivity: 0.1,
+            invert_y: false,
+            pitch_limit: crate::camera::DEFAULT_PITCH_LIMIT_DEG,
+            orbit_friction: crate::camera::DEFAULT_ORBIT_FRICTION,
+            aspect_lock: None,
+            near: crate::camera::DEFAULT_NEAR,
+            far: crate::camera::DEFAULT_FAR,
+            width: None,
+            height: None,
+            present_mode: PresentMode::Auto,
+            max_fps: None,
+            resolution_scale: 1.0,
+            target_frame_ms: None,
+            max_steps: 400,
+            step_size: 0.05,
+            shadow_softness: 1.0,
+            ring_filter_width: crate::scene::DEFAULT_RING_FILTER_WIDTH,
+            trace_logical_resolution: false,
+            backend: None,
+            adapter_name: None,
+            storage_format: None,
+            trace_shader: None,
+            trace_entry_point: None,
+            trace_constants: BTreeMap::new(),
+            gamma: None,
+            sharpen: 0.0,
+            lens_effects: false,
+            vignette_strength: 0.3,
+            aberration_strength: 0.5,
+            render_when_unfocused: false,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.6,
+            background: None,
+            skybox: None,
+            sky: None,
+            disk_ramp: None,
+            disk_emission: DiskEmission::Ramp,
+            disk_temp_inner: crate::scene::DEFAULT_DISK_TEMPS.0,
+            disk_temp_outer: crate::scene::DEFAULT_DISK_TEMPS.1,
+            seed: None,
+            holes: Vec::new(),
+            pose_given: false,
+        }
+    }
+}
+
+// Keys that place the camera.
+const POSE_KEYS: [&str; 4] = ["fov_y", "radius", "yaw", "pitch"];
+
+impl Config {
+    /// Looked up in the working directory, so `cargo run` picks up the
+    /// copy at the repository root.
+    pub fn default_path() -> PathBuf {
+        PathBuf::from("config.toml")
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let config: Self = toml::from_str(&text).map_err(|e| e.to_string().trim_end().to_owned())?;
+        let pose_given = toml::from_str::<toml::Table>(&text)
+            .is_ok_and(|table| POSE_KEYS.iter().any(|key| table.contains_key(*key)));
+        Ok(Self { pose_given, ..config })
+    }
+
+    /// Loads `path`, or the default location when `None`. A missing default
+    /// file is silent; any other failure is reported and the defaults used.
+    pub fn load(path: Option<&Path>) -> Self {
+        let default_path = Self::default_path();
+        let explicit = path.is_some();
+        let path = path.unwrap_or(&default_path);
+        if !explicit && !path.exists() {
+            return Self::default();
+        }
+        match Self::read(path) {
+            Ok(config) => {
+                log::info!("loaded config from {}", path.display());
+                config
+            }
+            Err(e) => {
+                log::warn!("failed to load config {}: {e}; using defaults", path.display());
+                Self::default()
+            }
+        }
+    }
+}
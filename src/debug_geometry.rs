@@ -0,0 +1,205 @@
+use std::f32::consts::TAU;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::scene::{Companions, SceneUbo, MAX_COMPANIONS};
+use crate::texture::depth_state;
+
+// Must match ESCAPE_RADIUS in trace.wgsl: rays leaving this sphere are sky.
+const ESCAPE_RADIUS: f32 = 100.0;
+// Line segments per circle.
+const SEGMENTS: usize = 96;
+// Two disk edges plus three great circles of the escape sphere, and one
+// three-axis cross per companion.
+const MAX_VERTICES: usize = 5 * SEGMENTS * 2 + MAX_COMPANIONS * 6;
+
+const DISK_COLOR: [f32; 3] = [1.0, 0.6, 0.2];
+const SPHERE_COLOR: [f32; 3] = [0.3, 0.6, 1.0];
+const COMPANION_COLOR: [f32; 3] = [0.9, 0.9, 0.9];
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Vertex {
+    pos: [f32; 3],
+    color: [f32; 3],
+}
+
+/// Line-list segments around a circle of `radius` about `center`, spanned
+/// by the unit vectors `u` and `v`.
+fn push_circle(out: &mut Vec<Vertex>, center: Vec3, (u, v): (Vec3, Vec3), radius: f32, color: [f32; 3]) {
+    let point = |i: usize| {
+        let (sin, cos) = (TAU * i as f32 / SEGMENTS as f32).sin_cos();
+        (center + radius * (cos * u + sin * v)).to_array()
+    };
+    for i in 0..SEGMENTS {
+        out.push(Vertex { pos: point(i), color });
+        out.push(Vertex { pos: point(i + 1), color });
+    }
+}
+
+/// Lines for the current scene, in world space.
+fn build_lines(scene: &SceneUbo, companions: &Companions) -> Vec<Vertex> {
+    let mut lines = Vec::with_capacity(MAX_VERTICES);
+    if scene.disk_enabled != 0 {
+        for radius in [scene.disk_inner, scene.disk_outer] {
+            push_circle(&mut lines, Vec3::ZERO, (Vec3::X, Vec3::Z), radius, DISK_COLOR);
+        }
+    }
+    for plane in [(Vec3::X, Vec3::Z), (Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z)] {
+        push_circle(&mut lines, Vec3::ZERO, plane, ESCAPE_RADIUS, SPHERE_COLOR);
+    }
+    // Arms reach past the horizon (2M) so they show around the shadow.
+    for hole in companions.holes() {
+        let center = Vec3::from(hole.position);
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            let arm = 4.0 * hole.mass * axis;
+            lines.push(Vertex { pos: (center - arm).to_array(), color: COMPANION_COLOR });
+            lines.push(Vertex { pos: (center + arm).to_array(), color: COMPANION_COLOR });
+        }
+    }
+    lines
+}
+
+/// World-space guides drawn after the blit and depth-tested against the
+/// traced image: the disk's edges, the escape sphere the trace integrates
+/// within, and a marker at each companion hole. Off by default.
+pub(crate) struct DebugGeometryPass {
+    view_proj: Mat4,
+    buf: wgpu::Buffer,
+    vertices: wgpu::Buffer,
+    vertex_count: u32,
+    // What `vertices` was built from; lines are only rebuilt on a change.
+    built_for: Option<(SceneUbo, Companions)>,
+    bg: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    pub(crate) visible: bool,
+}
+
+impl DebugGeometryPass {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("debug_geometry.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/debug_geometry.wgsl").into()),
+        });
+        let view_proj = Mat4::IDENTITY;
+        let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("debug_geometry_ubo"),
+            contents: bytemuck::bytes_of(&view_proj.to_cols_array_2d()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let vertices = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("debug_geometry_vertices"),
+            size: (MAX_VERTICES * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("debug_geometry_bgl"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("debug_geometry_bg"),
+            layout: &bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buf.as_entire_binding(),
+            }],
+        });
+        let pl = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug_geometry_pl"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("debug_geometry_pipeline"),
+            layout: Some(&pl),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: Some("vs"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: Some("fs"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            // Lines in the disk plane sit at the disk's own traced depth.
+            depth_stencil: Some(depth_state(false, wgpu::CompareFunction::LessEqual)),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            view_proj,
+            buf,
+            vertices,
+            vertex_count: 0,
+            built_for: None,
+            bg,
+            pipeline,
+            visible: false,
+        }
+    }
+
+    /// Follows the camera (`view_inv` and `proj_inv` as in `CameraUbo`) and
+    /// rebuilds the lines if the scene or companions changed.
+    pub(crate) fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        (view_inv, proj_inv): (Mat4, Mat4),
+        scene: &SceneUbo,
+        companions: &Companions,
+    ) {
+        let view_proj = proj_inv.inverse() * view_inv.inverse();
+        if view_proj != self.view_proj {
+            self.view_proj = view_proj;
+            queue.write_buffer(&self.buf, 0, bytemuck::bytes_of(&view_proj.to_cols_array_2d()));
+        }
+        if self.built_for != Some((*scene, *companions)) {
+            self.built_for = Some((*scene, *companions));
+            let lines = build_lines(scene, companions);
+            self.vertex_count = lines.len() as u32;
+            queue.write_buffer(&self.vertices, 0, bytemuck::cast_slice(&lines));
+        }
+    }
+
+    pub(crate) fn draw(&self, rpass: &mut wgpu::RenderPass<'_>) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bg, &[]);
+        rpass.set_vertex_buffer(0, self.vertices.slice(..));
+        rpass.draw(0..self.vertex_count, 0..1);
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::{Duration, Instant};
+
+use gilrs::{Axis, Button, EventType, Gilrs};
+use glam::Vec2;
+
+use crate::camera::CameraCtrl;
+
+// Stick travel ignored around the center, where pads rarely rest at zero.
+const DEADZONE: f32 = 0.15;
+// A fully deflected right stick orbits like a cursor drag this fast.
+const ORBIT_PIXELS_PER_SEC: f32 = 400.0;
+// A fully deflected left stick or trigger zooms like this many wheel lines.
+const ZOOM_LINES_PER_SEC: f32 = 4.0;
+/// How often the event loop wakes up to read the pads while otherwise idle.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Controller input mapped onto the mouse's orbit and zoom, so damping and
+/// the pitch limit apply the same way: right stick orbits, left stick Y or
+/// the triggers zoom, and South (A / Cross) resets the camera.
+pub struct Gamepad {
+    gilrs: Gilrs,
+    last_poll: Instant,
+}
+
+fn deadzone(v: f32) -> f32 {
+    if v.abs() < DEADZONE { 0.0 } else { v }
+}
+
+impl Gamepad {
+    pub fn new() -> Option<Self> {
+        match Gilrs::new() {
+            Ok(gilrs) => Some(Self { gilrs, last_poll: Instant::now() }),
+            Err(e) => {
+                log::warn!("gamepad support unavailable: {e}");
+                None
+            }
+        }
+    }
+
+    /// Drains pending events and applies the first connected pad's sticks
+    /// and triggers to `ctrl` for the time since the last poll.
+    pub fn poll(&mut self, ctrl: &mut CameraCtrl) {
+        let now = Instant::now();
+        let dt = (now - self.last_poll).as_secs_f32().min(0.1);
+        self.last_poll = now;
+
+        while let Some(event) = self.gilrs.next_event() {
+            if let EventType::ButtonPressed(Button::South, _) = event.event {
+                ctrl.reset();
+            }
+        }
+        let Some((_, pad)) = self.gilrs.gamepads().next() else {
+            return;
+        };
+        // Stick Y points up, screen Y down: pushing up drags upward.
+        let stick = Vec2::new(
+            deadzone(pad.value(Axis::RightStickX)),
+            -deadzone(pad.value(Axis::RightStickY)),
+        );
+        if stick != Vec2::ZERO {
+            ctrl.orbit(stick * ORBIT_PIXELS_PER_SEC * dt);
+        }
+        let trigger = |button| pad.button_data(button).map_or(0.0, |data| data.value());
+        let zoom = deadzone(pad.value(Axis::LeftStickY))
+            + trigger(Button::RightTrigger2)
+            - trigger(Button::LeftTrigger2);
+        if zoom != 0.0 {
+            ctrl.zoom(zoom * ZOOM_LINES_PER_SEC * dt);
+        }
+    }
+}
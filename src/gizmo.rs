@@ -0,0 +1,160 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+use wgpu::util::DeviceExt;
+
+use crate::texture::depth_state;
+
+// Side of the square corner viewport, and its distance from the window
+// edges, in physical pixels.
+const SIZE: f32 = 96.0;
+const MARGIN: f32 = 8.0;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Vertex {
+    pos: [f32; 3],
+    color: [f32; 3],
+}
+
+// One line per world axis, from the origin out to the unit vector.
+const AXES: [Vertex; 6] = [
+    Vertex { pos: [0.0, 0.0, 0.0], color: [1.0, 0.2, 0.2] },
+    Vertex { pos: [1.0, 0.0, 0.0], color: [1.0, 0.2, 0.2] },
+    Vertex { pos: [0.0, 0.0, 0.0], color: [0.2, 1.0, 0.2] },
+    Vertex { pos: [0.0, 1.0, 0.0], color: [0.2, 1.0, 0.2] },
+    Vertex { pos: [0.0, 0.0, 0.0], color: [0.3, 0.5, 1.0] },
+    Vertex { pos: [0.0, 0.0, 1.0], color: [0.3, 0.5, 1.0] },
+];
+
+/// XYZ axis lines in the bottom-right corner of the blit pass, turned with
+/// the camera so it's clear which way is up while orbiting.
+pub(crate) struct GizmoPass {
+    view: Mat4,
+    buf: wgpu::Buffer,
+    vertices: wgpu::Buffer,
+    bg: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    pub(crate) visible: bool,
+}
+
+impl GizmoPass {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("gizmo.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/gizmo.wgsl").into()),
+        });
+        let view = Mat4::IDENTITY;
+        let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("gizmo_ubo"),
+            contents: bytemuck::bytes_of(&view.to_cols_array_2d()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("gizmo_vertices"),
+            contents: bytemuck::cast_slice(&AXES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("gizmo_bgl"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("gizmo_bg"),
+            layout: &bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buf.as_entire_binding(),
+            }],
+        });
+        let pl = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("gizmo_pl"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("gizmo_pipeline"),
+            layout: Some(&pl),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: Some("vs"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: Some("fs"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: Some(depth_state(false, wgpu::CompareFunction::Always)),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            view,
+            buf,
+            vertices,
+            bg,
+            pipeline,
+            visible: true,
+        }
+    }
+
+    /// Follows the camera; `view_inv` is the matrix `compute_camera_mats`
+    /// returns. Only rotation matters, so translation is dropped.
+    pub(crate) fn update(&mut self, queue: &wgpu::Queue, view_inv: Mat4) {
+        let (_, rotation, _) = view_inv.inverse().to_scale_rotation_translation();
+        let view = Mat4::from_quat(rotation);
+        if view == self.view {
+            return;
+        }
+        self.view = view;
+        queue.write_buffer(&self.buf, 0, bytemuck::bytes_of(&view.to_cols_array_2d()));
+    }
+
+    /// Draws into a corner of a `screen`-sized target. Changes the pass's
+    /// viewport, so call it after anything that uses the full target.
+    pub(crate) fn draw(&self, rpass: &mut wgpu::RenderPass<'_>, screen: (u32, u32)) {
+        let (width, height) = (screen.0 as f32, screen.1 as f32);
+        let size = SIZE.min(width - 2.0 * MARGIN).min(height - 2.0 * MARGIN);
+        if size <= 0.0 {
+            return;
+        }
+        rpass.set_viewport(width - size - MARGIN, height - size - MARGIN, size, size, 0.0, 1.0);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bg, &[]);
+        rpass.set_vertex_buffer(0, self.vertices.slice(..));
+        rpass.draw(0..AXES.len() as u32, 0..1);
+    }
+}
+
@@ -0,0 +1,2482 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, UVec2, Vec2, Vec3};
+use half::f16;
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::background::{Background, Skybox};
+use crate::bloom::BloomPass;
+use crate::camera::{active_region, compute_camera_mats, CameraCtrl, CameraUbo};
+use crate::config::{Config, PresentMode, StorageFormat};
+use crate::debug_geometry::DebugGeometryPass;
+use crate::gizmo::GizmoPass;
+use crate::hud::{FrameBudget, FrameStats, HudPass};
+use crate::ramp::DiskRamp;
+use crate::scene::{Companions, SamplingUbo, SceneUbo, COMPARISONS, SKY_COLOR};
+use crate::snapshot::Snapshot;
+use crate::texture::{
+    create_depth_target, create_msaa_target, create_storage_texture, create_viewport_target, depth_state, hdr_to_rgba8, linear_to_srgb,
+    tonemap_aces, FrameCapture, HdrCapture, TRACE_DEPTH_FORMAT,
+};
+
+/// Measures compute and blit pass durations with timestamp queries, for
+/// adaptive resolution and printed every `REPORT_INTERVAL` readbacks. A
+/// traced frame is sampled whenever the previous readback has landed, and
+/// readback is asynchronous so profiling never stalls the frame.
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buf: wgpu::Buffer,
+    readback_buf: wgpu::Buffer,
+    // Nanoseconds per timestamp tick.
+    period_ns: f32,
+    frames: u32,
+    pending: Option<std::sync::mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+impl GpuTimer {
+    const REPORT_INTERVAL: u32 = 30;
+    // compute begin/end, blit begin/end
+    const QUERY_COUNT: u32 = 4;
+
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let size = (Self::QUERY_COUNT * wgpu::QUERY_SIZE) as u64;
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("pass_timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: Self::QUERY_COUNT,
+            }),
+            resolve_buf: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("timestamp_resolve_buf"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buf: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("timestamp_readback_buf"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            period_ns: queue.get_timestamp_period(),
+            frames: 0,
+            pending: None,
+        }
+    }
+
+    fn compute_writes(&self) -> wgpu::ComputePassTimestampWrites<'_> {
+        wgpu::ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    fn render_writes(&self) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(2),
+            end_of_pass_write_index: Some(3),
+        }
+    }
+
+    /// Records the query resolve unless the previous readback is still in
+    /// flight.
+    fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+        encoder.resolve_query_set(&self.query_set, 0..Self::QUERY_COUNT, &self.resolve_buf, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buf, 0, &self.readback_buf, 0, None);
+        true
+    }
+
+    fn start_readback(&mut self) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.readback_buf
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |res| {
+                let _ = tx.send(res);
+            });
+        self.pending = Some(rx);
+    }
+
+    /// Returns the compute pass duration once a readback lands.
+    fn poll(&mut self, device: &wgpu::Device) -> Option<Duration> {
+        let rx = self.pending.as_ref()?;
+        let _ = device.poll(wgpu::PollType::Poll);
+        match rx.try_recv() {
+            Ok(Ok(())) => {
+                let ticks: [u64; Self::QUERY_COUNT as usize] = {
+                    let data = self.readback_buf.slice(..).get_mapped_range();
+                    bytemuck::pod_read_unaligned(&data)
+                };
+                self.readback_buf.unmap();
+                let ms = |begin: u64, end: u64| {
+                    end.wrapping_sub(begin) as f64 * self.period_ns as f64 / 1.0e6
+                };
+                let compute_ms = ms(ticks[0], ticks[1]);
+                self.frames += 1;
+                if self.frames >= Self::REPORT_INTERVAL {
+                    self.frames = 0;
+                    log::info!("gpu: compute {:.3} ms, blit {:.3} ms", compute_ms, ms(ticks[2], ticks[3]));
+                }
+                self.pending = None;
+                Some(Duration::from_secs_f64(compute_ms / 1000.0))
+            }
+            Ok(Err(e)) => {
+                log::warn!("timestamp readback failed: {e}");
+                self.pending = None;
+                None
+            }
+            Err(_) => None,
+        }
+    }
+}
+
+// Trace output and accumulation format; float so highlights survive until
+// the blit tonemaps them and the running average doesn't band.
+const STORAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// Used instead on adapters that can't write `STORAGE_FORMAT` from a shader.
+const FALLBACK_STORAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+// Sample count for the blit/overlay pass when the surface format supports it.
+const MSAA_SAMPLES: u32 = 4;
+// Resolution scale change per adaptive-resolution step.
+const ADAPTIVE_SCALE_STEP: f32 = 0.05;
+// Exposure limits in stops either side of 1.0.
+const EXPOSURE_RANGE_EV: (f32, f32) = (-8.0, 8.0);
+// Blit sharpening strengths F12 cycles through.
+const SHARPEN_LEVELS: [f32; 3] = [0.0, 0.5, 1.0];
+// Lens effect strengths Ctrl+V and Ctrl+A cycle through; see blit.wgsl.
+const VIGNETTE_LEVELS: [f32; 4] = [0.0, 0.3, 0.6, 1.0];
+const ABERRATION_LEVELS: [f32; 4] = [0.0, 0.5, 1.0, 2.0];
+// Samples averaged before a still image is considered converged.
+const MAX_ACCUM: u32 = 64;
+/// What the blit and overlays draw to without a surface, and so the format
+/// of `render_to_viewport`'s texture for hosts that don't pass one.
+pub const VIEWPORT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// One step of Ctrl+Q's quality cycle: every knob that trades frame time
+/// for image quality, set together.
+struct QualityPreset {
+    name: &'static str,
+    resolution_scale: f32,
+    max_steps: u32,
+    max_accum: u32,
+    volumetric: bool,
+    jitter: bool,
+}
+
+// From integrated GPUs to offline-style renders; Balanced is roughly the
+// default config.
+const QUALITY_PRESETS: [QualityPreset; 4] = [
+    QualityPreset { name: "Performance", resolution_scale: 0.5, max_steps: 200, max_accum: 8, volumetric: false, jitter: false },
+    QualityPreset { name: "Balanced", resolution_scale: 1.0, max_steps: 400, max_accum: MAX_ACCUM, volumetric: false, jitter: true },
+    QualityPreset { name: "Quality", resolution_scale: 1.0, max_steps: 800, max_accum: 256, volumetric: true, jitter: true },
+    QualityPreset { name: "Cinematic", resolution_scale: 1.0, max_steps: 2000, max_accum: 1024, volumetric: true, jitter: true },
+];
+// Trace workgroup sizes timed at startup, and the pixel region each is
+// timed on.
+const WORKGROUP_CANDIDATES: [(u32, u32); 3] = [(8, 8), (16, 16), (8, 4)];
+const WORKGROUP_BENCH_TILE: u32 = 256;
+// Longest step `update` advances the camera by in one frame.
+const MAX_UPDATE_DT: f32 = 1.0 / 30.0;
+// How long the window size has to hold before the trace is rebuilt for it.
+const RESIZE_SETTLE: Duration = Duration::from_millis(100);
+// `HIT_DISK` in trace.wgsl: the only hit type with a point worth orbiting.
+const HIT_DISK: u32 = 2;
+
+#[derive(Debug)]
+pub enum InitError {
+    NoAdapter,
+    // No adapter's name contains `Config::adapter_name`.
+    NoMatchingAdapter(String),
+    Device(wgpu::RequestDeviceError),
+    Surface(wgpu::CreateSurfaceError),
+    Window(winit::error::OsError),
+}
+
+impl std::fmt::Display for InitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InitError::NoAdapter => write!(f, "No compatible GPU adapter found"),
+            InitError::NoMatchingAdapter(name) => write!(f, "no compatible GPU adapter named like \"{name}\""),
+            InitError::Device(e) => write!(f, "failed to create GPU device: {e}"),
+            InitError::Surface(e) => write!(f, "failed to create window surface: {e}"),
+            InitError::Window(e) => write!(f, "failed to create window: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for InitError {}
+
+pub struct GpuState {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    pub size: PhysicalSize<u32>,
+    // Non-vsync mode the V key toggles to (Mailbox, else Immediate).
+    uncapped_present_mode: wgpu::PresentMode,
+
+    // compute output, traced at `resolution_scale` of the surface size and
+    // upscaled by the blit's linear sampler
+    resolution_scale: f32,
+    // Steps `resolution_scale` to hold a frame-time target, when configured.
+    frame_budget: Option<FrameBudget>,
+    // Window DPI scale the trace is divided by when tracing at logical
+    // resolution; 1.0 traces every physical pixel.
+    logical_trace: bool,
+    trace_dpi_scale: f32,
+    // `Config::storage_format` if usable, else `STORAGE_FORMAT`, or the
+    // fallback where that isn't storage-capable
+    storage_format: wgpu::TextureFormat,
+    // Ping-ponged trace output: each trace writes the texture that isn't
+    // `front` and then makes it the front one, which bloom, the blit and
+    // readbacks read. A trace thus never writes what the frame before it
+    // is still showing.
+    storage: [(wgpu::Texture, wgpu::TextureView); 2],
+    front: usize,
+    // `CameraUbo::view_proj` of the trace now in the front texture, which
+    // the next trace gets as `prev_view_proj`.
+    front_view_proj: Mat4,
+    // View depth of each traced pixel, for depth-testing raster overlays.
+    trace_depth_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+
+    // Ping-ponged running average of the trace in full float precision;
+    // `storage` receives the same average quantised for display.
+    accum: [(wgpu::Texture, wgpu::TextureView); 2],
+    frame_index: u32,
+    max_accum: u32,
+    // Index into `QUALITY_PRESETS` last applied; `None` until one is.
+    quality_preset: Option<usize>,
+
+    // Linear scale applied before tonemapping, in powers of two.
+    exposure: f32,
+
+    // camera
+    pub camera_ctrl: CameraCtrl,
+    camera_buf: wgpu::Buffer,
+    last_ubo: CameraUbo,
+    // Sampling state last written after the scene in both scene buffers.
+    last_sampling: SamplingUbo,
+    // Shader time of the last frame, reused when a resize rewrites the
+    // camera UBO between frames.
+    last_time: f32,
+
+    // black-hole parameters; `last_scene` is what the GPU copy holds
+    pub scene: SceneUbo,
+    last_scene: SceneUbo,
+    scene_buf: wgpu::Buffer,
+    // Split-screen A/B view: the right half traces `scene.compared(comparison)`,
+    // uploaded to `right_scene_buf`, which holds `scene` again when it's off.
+    split: bool,
+    comparison: usize,
+    last_right_scene: SceneUbo,
+    right_scene_buf: wgpu::Buffer,
+    // extra holes, uploaded the same way
+    pub companions: Companions,
+    last_companions: Companions,
+    companions_buf: wgpu::Buffer,
+    // Hit point of one pixel, written by the trace on request, and where
+    // it's copied for mapping.
+    pick_buf: wgpu::Buffer,
+    pick_readback: wgpu::Buffer,
+
+    // Set when the traced image is out of date (camera moved, resized); the
+    // compute pass is skipped otherwise and the blit reuses the front
+    // `storage` texture.
+    dirty: bool,
+    // When the surface was last resized, while the trace still has the size
+    // from before; see `apply_pending_resize`.
+    resize_pending: Option<Instant>,
+    // Set while the window is in the background: no redraws are requested
+    // and frames that still arrive skip the trace.
+    pub paused: bool,
+    // True if the trace shader's output depends on `time`, in which case
+    // every frame has to be traced. The current shader is static.
+    animated: bool,
+    // Sub-pixel jitter for anti-aliasing; off traces every sample through
+    // the pixel center.
+    jitter: bool,
+    // Sampling seed; see `SamplingUbo::seed`.
+    seed: u32,
+    // Keeps the last camera upload while set, for comparing shader edits
+    // against a fixed frame; `camera_ctrl` still follows input meanwhile.
+    camera_frozen: bool,
+
+    // compute
+    compute_bgl: wgpu::BindGroupLayout,
+    // Indexed by the `storage` texture written, then `frame_index % 2`:
+    // group [o][i] writes storage[o], reads accum[i] and writes the other,
+    // and samples the previous frame from the other storage texture.
+    compute_bgs: [[wgpu::BindGroup; 2]; 2],
+    background_bg: wgpu::BindGroup,
+    // A real skybox was loaded, so F1 offers it.
+    has_skybox: bool,
+    disk_ramp: DiskRamp,
+    compute_pipeline: wgpu::ComputePipeline,
+    // Tile size `compute_pipeline` was built for.
+    workgroup: (u32, u32),
+
+    // display passes, drawing in `config.format`
+    bloom: BloomPass,
+    blit: BlitPass,
+    hud: HudPass,
+    gizmo: GizmoPass,
+    debug_geometry: DebugGeometryPass,
+    // Offscreen target for `render_to_viewport`, sized by `resize_viewport`
+    // independently of any surface.
+    viewport: Option<(wgpu::Texture, wgpu::TextureView)>,
+
+    timer: Option<GpuTimer>,
+    // Why the device was lost, from wgpu's device-lost callback; only
+    // registered on devices built by `new`.
+    device_lost: Arc<Mutex<Option<String>>>,
+}
+
+/// A pixel's hit point, filled in by the trace while `requested` is set;
+/// mirrors `Pick` in trace.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct PickBuffer {
+    pixel: [u32; 2],
+    requested: u32,
+    hit: u32,
+    position: [f32; 3],
+    _pad: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct DisplayUbo {
+    exposure: f32,
+    gamma: f32,
+    encoding: u32,
+    crosshair: u32,
+    // Draws the split-screen divider.
+    split: u32,
+    // Strength of the blit's sharpening of the upscaled trace, 0 to 1.
+    sharpen: f32,
+    // Lens effects: corner darkening and color fringing, 0 when off.
+    vignette: f32,
+    aberration: f32,
+    // Rows of the projection that map view z to clip z and w; see
+    // `ndc_depth` in blit.wgsl.
+    depth_proj: [f32; 4],
+    // Where the trace goes on the surface, (x, y, width, height) in pixels;
+    // the letterbox bars around it are black.
+    region: [f32; 4],
+}
+
+/// How the blit encodes its output; mirrors the `ENCODE_*` constants in
+/// blit.wgsl.
+#[derive(Clone, Copy)]
+enum Encoding {
+    // sRGB surface format: the hardware encodes on write.
+    Hardware = 0,
+    // Linear format: the shader applies the sRGB transfer curve...
+    Srgb = 1,
+    // ...or a plain power curve when `gamma` is configured.
+    Gamma = 2,
+}
+
+/// The blit's optional lens effects. The strengths are kept while the
+/// effects are toggled off, so turning them back on restores the look.
+#[derive(Clone, Copy)]
+struct LensEffects {
+    enabled: bool,
+    vignette: f32,
+    aberration: f32,
+}
+
+impl LensEffects {
+    /// (vignette, aberration) as the shader gets them.
+    fn strengths(&self) -> (f32, f32) {
+        if self.enabled { (self.vignette, self.aberration) } else { (0.0, 0.0) }
+    }
+}
+
+/// Fullscreen-triangle pass that tonemaps the trace onto the surface.
+struct BlitPass {
+    render_bgl: wgpu::BindGroupLayout,
+    // One per `storage` texture.
+    render_bgs: [wgpu::BindGroup; 2],
+    display_buf: wgpu::Buffer,
+    display_bg: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    // Output encoding, and the exponent `Encoding::Gamma` uses.
+    encoding: Encoding,
+    gamma: f32,
+    // One-pixel cross at the surface's center, for framing.
+    crosshair: bool,
+    sharpen: f32,
+    lens: LensEffects,
+    // Multisampled target the blit and overlays draw into before resolving
+    // to the surface; `None` at 1x.
+    sample_count: u32,
+    msaa_view: Option<wgpu::TextureView>,
+    // Filled from the trace's depth by the blit, for overlays to test against.
+    depth_view: wgpu::TextureView,
+}
+
+impl BlitPass {
+    /// What the blit writes for a flat linear `color`, so the clear matches
+    /// a solid-color sky.
+    fn clear_color(&self, color: [f32; 3], exposure: f32) -> wgpu::Color {
+        let [r, g, b] = color.map(|c| {
+            let ldr = tonemap_aces(c * exposure);
+            f64::from(match self.encoding {
+                Encoding::Hardware => ldr,
+                Encoding::Srgb => linear_to_srgb(ldr),
+                Encoding::Gamma => ldr.powf(1.0 / self.gamma),
+            })
+        });
+        wgpu::Color { r, g, b, a: 1.0 }
+    }
+
+    fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        // both of the trace's color outputs, and its depth
+        (storage_views, trace_depth_view): ([&wgpu::TextureView; 2], &wgpu::TextureView),
+        sampler: &wgpu::Sampler,
+        bloom: &BloomPass,
+        (encoding, gamma, sharpen, lens): (Encoding, f32, f32, LensEffects),
+        sample_count: u32,
+    ) -> Self {
+        let blit_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("blit.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/blit.wgsl").into()),
+        });
+
+        // Render pipeline (fullscreen triangle)
+        let render_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("render_bgl"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        // Tonemapping parameters, kept apart from the texture bindings so
+        // they survive storage resizes.
+        let display_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("display_bgl"),
+            entries: &[0, 1].map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }),
+        });
+        let display_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("display_ubo"),
+            contents: bytemuck::bytes_of(&DisplayUbo {
+                exposure: 1.0,
+                gamma,
+                encoding: encoding as u32,
+                crosshair: 0,
+                split: 0,
+                sharpen,
+                vignette: lens.strengths().0,
+                aberration: lens.strengths().1,
+                depth_proj: [0.0, 0.0, 0.0, 1.0],
+                region: [0.0, 0.0, config.width as f32, config.height as f32],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let display_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("display_bg"),
+            layout: &display_bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: display_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: bloom.buf.as_entire_binding(),
+                },
+            ],
+        });
+        let render_pl = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("render_pl"),
+            bind_group_layouts: &[&render_bgl, &display_bgl],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("blit_pipeline"),
+            layout: Some(&render_pl),
+            vertex: wgpu::VertexState {
+                module: &blit_module,
+                entry_point: Some("vs"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &blit_module,
+                entry_point: Some("fs"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Writes the traced depth for everything drawn after it.
+            depth_stencil: Some(depth_state(true, wgpu::CompareFunction::Always)),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+        let render_bgs = storage_views.map(|storage_view| {
+            create_render_bind_group(device, &render_bgl, (storage_view, trace_depth_view), bloom.view(), sampler)
+        });
+
+        Self {
+            render_bgl,
+            render_bgs,
+            display_buf,
+            display_bg,
+            render_pipeline,
+            encoding,
+            gamma,
+            crosshair: false,
+            sharpen,
+            lens,
+            sample_count,
+            msaa_view: create_msaa_target(device, config, sample_count),
+            depth_view: create_depth_target(device, config, sample_count),
+        }
+    }
+}
+
+impl GpuState {
+    /// Builds the renderer for `surface`, or for offscreen rendering at
+    /// `size` when `surface` is `None`, with the blit drawing in
+    /// `VIEWPORT_FORMAT` for `render_to_viewport`.
+    pub async fn new(
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'_>>,
+        size: PhysicalSize<u32>,
+        startup: &Config,
+    ) -> Result<Self, InitError> {
+        let adapter = match &startup.adapter_name {
+            Some(name) => Self::find_adapter(instance, surface, name)?,
+            None => Self::pick_adapter(instance, surface).await?,
+        };
+        let info = adapter.get_info();
+        log::info!("adapter: {} ({:?}, {:?})", info.name, info.backend, info.device_type);
+        // Timestamp queries are optional; without them profiling is skipped.
+        // Filtering 32-bit float textures is for `StorageFormat::Rgba32Float`.
+        let optional_features =
+            adapter.features() & (wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::FLOAT32_FILTERABLE);
+        // WebGPU's default limits first; constrained integrated and mobile
+        // GPUs may fall short of them, so then settle for what the adapter
+        // has rather than failing to start.
+        let mut device = Err(None);
+        for (profile, required_limits) in [("WebGPU default", wgpu::Limits::default()), ("adapter", adapter.limits())] {
+            let request = adapter
+                .request_device(&wgpu::DeviceDescriptor {
+                    label: Some("device"),
+                    required_features: optional_features,
+                    required_limits,
+                    ..Default::default()
+                })
+                .await;
+            match request {
+                Ok(found) => {
+                    log::info!("device limits: {profile}");
+                    device = Ok(found);
+                    break;
+                }
+                Err(e) => {
+                    log::warn!("device with {profile} limits unavailable: {e}");
+                    device = Err(Some(e));
+                }
+            }
+        }
+        let (device, queue) = device.map_err(|e| InitError::Device(e.expect("at least one profile tried")))?;
+        let state = Self::with_device(&adapter, &device, &queue, surface, size, startup);
+        // Dropping the device reports `Destroyed`; only a real loss counts.
+        let device_lost = Arc::clone(&state.device_lost);
+        device.set_device_lost_callback(move |reason, message| {
+            if reason != wgpu::DeviceLostReason::Destroyed {
+                log::error!("GPU device lost ({reason:?}): {message}");
+                if let Ok(mut lost) = device_lost.lock() {
+                    *lost = Some(format!("{reason:?}: {message}"));
+                }
+            }
+        });
+        Ok(state)
+    }
+
+    /// The adapter `new` uses unless one is asked for by name: real
+    /// hardware if there is any, else a software fallback.
+    async fn pick_adapter(
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'_>>,
+    ) -> Result<wgpu::Adapter, InitError> {
+        for force_fallback_adapter in [false, true] {
+            let request = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    force_fallback_adapter,
+                    compatible_surface: surface,
+                })
+                .await;
+            if let Ok(found) = request {
+                return Ok(found);
+            }
+        }
+        Err(InitError::NoAdapter)
+    }
+
+    /// The first adapter whose name contains `name`, ignoring case, that can
+    /// present to `surface`. Lists the adapters there are when none match.
+    fn find_adapter(
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'_>>,
+        name: &str,
+    ) -> Result<wgpu::Adapter, InitError> {
+        let adapters = instance.enumerate_adapters(wgpu::Backends::all());
+        let wanted = name.to_lowercase();
+        let found = adapters.iter().find(|adapter| {
+            adapter.get_info().name.to_lowercase().contains(&wanted)
+                && surface.is_none_or(|surface| adapter.is_surface_supported(surface))
+        });
+        match found {
+            Some(adapter) => Ok(adapter.clone()),
+            None => {
+                for adapter in &adapters {
+                    let info = adapter.get_info();
+                    log::warn!("available adapter: {} ({:?})", info.name, info.backend);
+                }
+                Err(InitError::NoMatchingAdapter(name.to_owned()))
+            }
+        }
+    }
+
+    /// Builds the renderer on a device the caller already owns, e.g. when
+    /// embedding it in another wgpu app. The handles are cloned, not taken;
+    /// `surface`, if any, is configured here and again on every resize, so
+    /// a host drawing its own UI to the surface passes `None` and shows the
+    /// image from `render_to_viewport`.
+    pub fn with_device(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface: Option<&wgpu::Surface<'_>>,
+        size: PhysicalSize<u32>,
+        startup: &Config,
+    ) -> Self {
+        let (device, queue) = (device.clone(), queue.clone());
+
+        // Surface config. Without a surface it's a nominal one purely as
+        // the source of the output size and format; it is never applied.
+        let mut config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: VIEWPORT_FORMAT,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        let mut uncapped_present_mode = wgpu::PresentMode::Fifo;
+        if let Some(surface) = surface {
+            let caps = surface.get_capabilities(adapter);
+            config.format = caps
+                .formats
+                .iter()
+                .copied()
+                .find(|f| f.is_srgb())
+                .unwrap_or(caps.formats[0]);
+            // Mailbox gives low latency without tearing; Fifo is always available.
+            uncapped_present_mode = [wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate]
+                .into_iter()
+                .find(|m| caps.present_modes.contains(m))
+                .unwrap_or(wgpu::PresentMode::Fifo);
+            let requested = match startup.present_mode {
+                PresentMode::Auto => wgpu::PresentMode::Mailbox,
+                PresentMode::Fifo => wgpu::PresentMode::Fifo,
+                PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+                PresentMode::Immediate => wgpu::PresentMode::Immediate,
+            };
+            if caps.present_modes.contains(&requested) {
+                config.present_mode = requested;
+            } else if startup.present_mode != PresentMode::Auto {
+                log::warn!("present mode {requested:?} unsupported; using Fifo");
+            }
+            config.alpha_mode = caps.alpha_modes[0];
+            log::info!("present mode: {:?}", config.present_mode);
+            surface.configure(&device, &config);
+        }
+
+        let timer = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| GpuTimer::new(&device, &queue));
+
+        // Storage texture for compute
+        let storage_format = pick_storage_format(adapter, &device, startup.storage_format);
+        let resolution_scale = startup.resolution_scale.clamp(0.25, 1.0);
+        let camera_ctrl = CameraCtrl::from_config(startup);
+        let [_, _, region_width, region_height] =
+            active_region(camera_ctrl.aspect_lock, config.width, config.height);
+        let (trace_width, trace_height) = scaled_size(
+            region_width,
+            region_height,
+            resolution_scale,
+            device.limits().max_texture_dimension_2d,
+        );
+        let storage = create_storage_textures(&device, trace_width, trace_height, storage_format);
+        let (_, trace_depth_view) =
+            create_storage_texture(&device, "trace_depth", trace_width, trace_height, TRACE_DEPTH_FORMAT);
+        let accum = create_accum_textures(&device, trace_width, trace_height, storage_format);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("blit_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // Camera UBO
+        let seed = startup.seed.unwrap_or_else(|| {
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+            now.map_or(0, |t| t.subsec_nanos() ^ t.as_secs() as u32)
+        });
+        log::info!("sampling seed: {seed} (--seed {seed} reproduces this run)");
+        let (view_inv, proj_inv) = compute_camera_mats(&camera_ctrl, config.width, config.height);
+        let view_proj = proj_inv.inverse() * view_inv.inverse();
+        let ubo = CameraUbo {
+            view_inv: view_inv.to_cols_array_2d(),
+            proj_inv: proj_inv.to_cols_array_2d(),
+            params: [trace_width as f32, trace_height as f32, 0.0],
+            ..CameraUbo::zeroed()
+        };
+        let camera_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("camera_ubo"),
+            contents: bytemuck::bytes_of(&ubo),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // Scene UBO, with the sampling state after the scene
+        let scene = SceneUbo::new(startup);
+        let sampling = SamplingUbo {
+            frame_index: 0,
+            aperture: camera_ctrl.aperture,
+            focus_distance: camera_ctrl.focus_distance,
+            jitter: 1,
+            seed,
+            prev_view_proj: view_proj.to_cols_array_2d(),
+            ..SamplingUbo::zeroed()
+        };
+        let scene_contents = [bytemuck::bytes_of(&scene), bytemuck::bytes_of(&sampling)].concat();
+        let scene_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("scene_ubo"),
+            contents: &scene_contents,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let right_scene_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("right_scene_ubo"),
+            contents: &scene_contents,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let companions = Companions::new(startup);
+        let companions_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("companions"),
+            contents: bytemuck::bytes_of(&companions),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let pick_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("pick"),
+            contents: bytemuck::bytes_of(&PickBuffer::zeroed()),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        let pick_readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pick_readback"),
+            size: std::mem::size_of::<PickBuffer>() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        // Compute pipeline
+        let compute_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("compute_bgl"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: storage_format,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: storage_format,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: TRACE_DEPTH_FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        // Sky for escaped rays, in its own group since it never changes.
+        let background = match startup.background.as_deref() {
+            Some(path) => Background::load(path, device.limits().max_texture_dimension_2d)
+                .unwrap_or_else(|e| {
+                    log::warn!("failed to load background {}: {e}; using procedural stars", path.display());
+                    Background::procedural()
+                }),
+            None => Background::procedural(),
+        };
+        let (_, background_view) = background.upload(&device, &queue);
+        let skybox = match startup.skybox.as_deref() {
+            Some(dir) => Skybox::load(dir, device.limits().max_texture_dimension_2d)
+                .unwrap_or_else(|e| {
+                    log::warn!("failed to load skybox: {e}; using the background");
+                    Skybox::placeholder()
+                }),
+            None => Skybox::placeholder(),
+        };
+        let has_skybox = !skybox.is_placeholder();
+        let (_, skybox_view) = skybox.upload(&device, &queue);
+        let skybox_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("skybox_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let background_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("background_sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let background_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("background_bgl"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let background_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("background_bg"),
+            layout: &background_bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&background_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&background_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&skybox_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&skybox_sampler),
+                },
+            ],
+        });
+
+        let disk_ramp = DiskRamp::new(&device, &queue, startup.disk_ramp.as_deref());
+
+        let compute_pl = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("compute_pl"),
+            bind_group_layouts: &[&compute_bgl, &background_bgl, &disk_ramp.bgl],
+            push_constant_ranges: &[],
+        });
+        let trace_shader = TraceShader::from_config(&device, &compute_pl, startup, storage_format);
+        let compute_bgs = create_compute_bind_groups(
+            &device,
+            &compute_bgl,
+            (&storage, &trace_depth_view),
+            &camera_buf,
+            [&scene_buf, &right_scene_buf, &companions_buf, &pick_buf],
+            &accum,
+        );
+        let (workgroup, compute_pipeline) = match trace_shader.fixed_workgroup {
+            Some(workgroup) => (workgroup, create_trace_pipeline(&device, &compute_pl, &trace_shader, (8, 8))),
+            None => pick_workgroup_size(
+                &device,
+                &queue,
+                &compute_pl,
+                &trace_shader,
+                [&compute_bgs[0][0], &background_bg, &disk_ramp.bg],
+                (trace_width, trace_height),
+            ),
+        };
+
+        let bloom = BloomPass::new(
+            &device,
+            [&storage[0].1, &storage[1].1],
+            trace_width,
+            trace_height,
+            storage_format,
+            startup.bloom_threshold,
+            startup.bloom_intensity,
+        );
+        // 4x MSAA for the blit and overlays where the output format allows it.
+        let sample_count = if adapter
+            .get_texture_format_features(config.format)
+            .flags
+            .sample_count_supported(MSAA_SAMPLES)
+        {
+            MSAA_SAMPLES
+        } else {
+            1
+        };
+        // sRGB surfaces encode on write; others need it done in the shader.
+        let (encoding, gamma) = if config.format.is_srgb() {
+            (Encoding::Hardware, 1.0)
+        } else {
+            log::info!("surface format {:?} is linear; encoding in the blit shader", config.format);
+            match startup.gamma {
+                Some(gamma) => (Encoding::Gamma, gamma.max(0.1)),
+                None => (Encoding::Srgb, 1.0),
+            }
+        };
+        let lens = LensEffects {
+            enabled: startup.lens_effects,
+            vignette: startup.vignette_strength.clamp(0.0, 1.0),
+            aberration: startup.aberration_strength.max(0.0),
+        };
+        let display = (encoding, gamma, startup.sharpen.clamp(0.0, 1.0), lens);
+        let traced = ([&storage[0].1, &storage[1].1], &trace_depth_view);
+        let blit = BlitPass::new(&device, &config, traced, &sampler, &bloom, display, sample_count);
+        let hud = HudPass::new(&device, config.format, sample_count);
+        let gizmo = GizmoPass::new(&device, config.format, sample_count);
+        let debug_geometry = DebugGeometryPass::new(&device, config.format, sample_count);
+
+        Self {
+            device,
+            queue,
+            config,
+            size,
+            uncapped_present_mode,
+            resolution_scale,
+            frame_budget: startup.target_frame_ms.map(FrameBudget::new),
+            logical_trace: startup.trace_logical_resolution,
+            trace_dpi_scale: 1.0,
+            storage_format,
+            storage,
+            front: 0,
+            front_view_proj: view_proj,
+            trace_depth_view,
+            sampler,
+            accum,
+            frame_index: 0,
+            max_accum: MAX_ACCUM,
+            quality_preset: None,
+            camera_ctrl,
+            camera_buf,
+            last_ubo: ubo,
+            last_sampling: sampling,
+            last_time: 0.0,
+            scene,
+            last_scene: scene,
+            scene_buf,
+            split: false,
+            comparison: 0,
+            last_right_scene: scene,
+            right_scene_buf,
+            companions,
+            last_companions: companions,
+            companions_buf,
+            pick_buf,
+            pick_readback,
+            dirty: true,
+            resize_pending: None,
+            paused: false,
+            animated: false,
+            jitter: true,
+            seed,
+            camera_frozen: false,
+            compute_bgl,
+            compute_bgs,
+            background_bg,
+            has_skybox,
+            disk_ramp,
+            compute_pipeline,
+            workgroup,
+            exposure: 1.0,
+            bloom,
+            blit,
+            hud,
+            gizmo,
+            debug_geometry,
+            viewport: None,
+            timer,
+            device_lost: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Why the device was lost, once wgpu reports it. Nothing drawn after
+    /// that reaches the screen; `recover` rebuilds on a new device.
+    pub fn device_lost(&self) -> Option<String> {
+        self.device_lost.lock().ok()?.clone()
+    }
+
+    /// Replaces everything with a fresh `new` renderer after a device loss,
+    /// carrying over the camera and the settings `snapshot` records so the
+    /// view is the one the user had.
+    pub async fn recover(
+        &mut self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'_>>,
+        startup: &Config,
+    ) -> Result<(), InitError> {
+        let mut fresh = Self::new(instance, surface, self.size, startup).await?;
+        fresh.restore(&self.snapshot());
+        std::mem::swap(&mut fresh.camera_ctrl, &mut self.camera_ctrl);
+        fresh.trace_dpi_scale = self.trace_dpi_scale;
+        fresh.paused = self.paused;
+        // Runtime toggles the snapshot doesn't record.
+        fresh.quality_preset = self.quality_preset;
+        fresh.max_accum = self.max_accum;
+        fresh.jitter = self.jitter;
+        fresh.split = self.split;
+        fresh.comparison = self.comparison;
+        fresh.bloom.params.enabled = self.bloom.params.enabled;
+        fresh.queue.write_buffer(&fresh.bloom.buf, 0, bytemuck::bytes_of(&fresh.bloom.params));
+        fresh.blit.crosshair = self.blit.crosshair;
+        fresh.blit.sharpen = self.blit.sharpen;
+        fresh.blit.lens = self.blit.lens;
+        fresh.hud.visible = self.hud.visible;
+        fresh.gizmo.visible = self.gizmo.visible;
+        fresh.debug_geometry.visible = self.debug_geometry.visible;
+        // The storage size follows the camera's aspect lock and the DPI scale.
+        fresh.recreate_storage();
+        fresh.write_display_ubo();
+        *self = fresh;
+        Ok(())
+    }
+
+    pub fn resize(&mut self, surface: &wgpu::Surface<'_>, new_size: PhysicalSize<u32>) {
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+        log::debug!("resizing surface to {}x{}", new_size.width, new_size.height);
+        self.size = new_size;
+        self.set_output_size(new_size);
+        surface.configure(&self.device, &self.config);
+    }
+
+    /// Points the blit, overlays and trace at an output of `size`, the
+    /// surface's or the viewport's. Dragging a window edge or a panel
+    /// divider resizes many times a second, so the targets wait for the
+    /// next frame and the trace for the size to settle; until then the old
+    /// trace is stretched over the output.
+    fn set_output_size(&mut self, size: PhysicalSize<u32>) {
+        if (self.config.width, self.config.height) == (size.width, size.height) {
+            return;
+        }
+        self.config.width = size.width;
+        self.config.height = size.height;
+        self.write_display_ubo();
+        self.resize_pending = Some(Instant::now());
+    }
+
+    fn resize_attachments(&mut self) {
+        let blit = &mut self.blit;
+        blit.msaa_view = create_msaa_target(&self.device, &self.config, blit.sample_count);
+        blit.depth_view = create_depth_target(&self.device, &self.config, blit.sample_count);
+    }
+
+    /// Catches up on a deferred resize before a frame: the attachments
+    /// have to match the output now, the trace once the output has kept
+    /// its size for `RESIZE_SETTLE`.
+    fn apply_pending_resize(&mut self) {
+        let Some(resized_at) = self.resize_pending else {
+            return;
+        };
+        let attachment_size = self.blit.depth_view.texture().size();
+        if (attachment_size.width, attachment_size.height) != (self.config.width, self.config.height) {
+            self.resize_attachments();
+        }
+        if resized_at.elapsed() >= RESIZE_SETTLE {
+            self.resize_pending = None;
+            self.recreate_storage();
+        }
+    }
+
+    /// Steps `resolution_scale` by `delta`, rebuilding the trace target.
+    pub fn step_resolution_scale(&mut self, delta: f32) {
+        let scale = (self.resolution_scale + delta).clamp(0.25, 1.0);
+        if scale == self.resolution_scale {
+            return;
+        }
+        self.resolution_scale = scale;
+        self.recreate_storage();
+        log::info!(
+            "resolution scale: {scale:.2} ({}x{})",
+            self.storage_tex().width(),
+            self.storage_tex().height()
+        );
+    }
+
+    /// Applies the next of `QUALITY_PRESETS`, retracing from scratch.
+    pub fn cycle_quality_preset(&mut self) {
+        let next = self.quality_preset.map_or(0, |i| (i + 1) % QUALITY_PRESETS.len());
+        let preset = &QUALITY_PRESETS[next];
+        self.quality_preset = Some(next);
+        self.scene.max_steps = preset.max_steps;
+        self.scene.volumetric = preset.volumetric as u32;
+        self.jitter = preset.jitter;
+        self.max_accum = preset.max_accum;
+        if preset.resolution_scale != self.resolution_scale {
+            self.resolution_scale = preset.resolution_scale;
+            self.recreate_storage();
+        }
+        self.dirty = true;
+        log::info!("quality: {}", preset.name);
+    }
+
+    /// Name of the quality preset last applied, if any.
+    pub fn quality_preset(&self) -> Option<&'static str> {
+        self.quality_preset.map(|i| QUALITY_PRESETS[i].name)
+    }
+
+    /// Advances everything that moves on its own by the wall-clock time
+    /// since the last frame, before `render`: the camera's damping, fly
+    /// keys, auto-orbit and momentum.
+    pub fn update(&mut self, frame_time: Duration) {
+        // Clamp so a stall, or the first frame after idling, doesn't
+        // teleport the camera.
+        let dt = frame_time.as_secs_f32().min(MAX_UPDATE_DT);
+        self.camera_ctrl.update(dt);
+    }
+
+    /// Feeds a trace time to adaptive resolution, shrinking the trace
+    /// quickly when over budget and growing it back slowly.
+    fn adapt_resolution(&mut self, trace_time: Duration) {
+        let Some(step) = self.frame_budget.as_mut().and_then(|b| b.record(trace_time)) else {
+            return;
+        };
+        let delta = if step < 0 { -ADAPTIVE_SCALE_STEP } else { ADAPTIVE_SCALE_STEP };
+        self.step_resolution_scale(delta);
+    }
+
+    /// Records the window's DPI scale. Only matters when tracing at logical
+    /// resolution, where a new scale means a new trace size.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        let dpi_scale = if self.logical_trace { scale_factor.max(1.0) as f32 } else { 1.0 };
+        if dpi_scale != self.trace_dpi_scale {
+            self.trace_dpi_scale = dpi_scale;
+            self.recreate_storage();
+        }
+    }
+
+    /// Recreates the storage texture at the current surface size, aspect
+    /// lock and resolution scale, along with the bind groups that reference
+    /// it.
+    fn recreate_storage(&mut self) {
+        let [_, _, region_width, region_height] = self.active_region();
+        let (width, height) =
+            scaled_size(
+                region_width,
+                region_height,
+                self.resolution_scale / self.trace_dpi_scale,
+                self.device.limits().max_texture_dimension_2d,
+            );
+        self.storage = create_storage_textures(&self.device, width, height, self.storage_format);
+        self.front = 0;
+        let (_, depth_view) =
+            create_storage_texture(&self.device, "trace_depth", width, height, TRACE_DEPTH_FORMAT);
+        self.trace_depth_view = depth_view;
+        self.accum = create_accum_textures(&self.device, width, height, self.storage_format);
+        self.dirty = true;
+
+        self.compute_bgs = create_compute_bind_groups(
+            &self.device,
+            &self.compute_bgl,
+            (&self.storage, &self.trace_depth_view),
+            &self.camera_buf,
+            [&self.scene_buf, &self.right_scene_buf, &self.companions_buf, &self.pick_buf],
+            &self.accum,
+        );
+        let storage_views = [&self.storage[0].1, &self.storage[1].1];
+        self.bloom.resize(&self.device, storage_views, width, height);
+        self.blit.render_bgs = storage_views.map(|storage_view| {
+            create_render_bind_group(
+                &self.device,
+                &self.blit.render_bgl,
+                (storage_view, &self.trace_depth_view),
+                self.bloom.view(),
+                &self.sampler,
+            )
+        });
+
+        self.update_camera_buffer(self.last_time);
+    }
+
+    pub fn toggle_vsync(&mut self, surface: &wgpu::Surface<'_>) {
+        self.config.present_mode = if self.config.present_mode == wgpu::PresentMode::Fifo {
+            self.uncapped_present_mode
+        } else {
+            wgpu::PresentMode::Fifo
+        };
+        surface.configure(&self.device, &self.config);
+        log::info!("present mode: {:?}", self.config.present_mode);
+    }
+
+    fn update_camera_buffer(&mut self, time: f32) {
+        // A new trace size still goes through, since the upload sets the
+        // grid the trace covers.
+        let trace_size = [self.storage_tex().width() as f32, self.storage_tex().height() as f32];
+        if self.camera_frozen && self.last_ubo.params[..2] == trace_size {
+            return;
+        }
+        self.last_time = time;
+        let (mut view_inv, mut proj_inv) =
+            compute_camera_mats(&self.camera_ctrl, self.config.width, self.config.height);
+        // A NaN here would blank every pixel until the next good pose, so
+        // keep showing the last one instead.
+        if !view_inv.is_finite() || !proj_inv.is_finite() {
+            log::warn!("camera matrices aren't finite; keeping the previous view");
+            view_inv = Mat4::from_cols_array_2d(&self.last_ubo.view_inv);
+            proj_inv = Mat4::from_cols_array_2d(&self.last_ubo.proj_inv);
+        }
+        let ubo = CameraUbo {
+            view_inv: view_inv.to_cols_array_2d(),
+            proj_inv: proj_inv.to_cols_array_2d(),
+            params: [trace_size[0], trace_size[1], time],
+            ..CameraUbo::zeroed()
+        };
+        let mut sampling = SamplingUbo {
+            frame_index: 0,
+            aperture: self.camera_ctrl.aperture,
+            focus_distance: self.camera_ctrl.focus_distance,
+            jitter: self.jitter as u32,
+            seed: self.seed,
+            prev_view_proj: self.front_view_proj.to_cols_array_2d(),
+            ..SamplingUbo::zeroed()
+        };
+        if self.animated || !ubo.same_view(&self.last_ubo) || !sampling.same_samples(&self.last_sampling) {
+            self.dirty = true;
+        }
+        if self.dirty {
+            self.frame_index = 0;
+        }
+        sampling.frame_index = self.frame_index;
+        debug_assert!(
+            ubo.view_inv.iter().chain(&ubo.proj_inv).flatten().all(|c| c.is_finite()),
+            "non-finite camera matrices"
+        );
+        let reprojected = ubo.proj_inv != self.last_ubo.proj_inv;
+        self.last_ubo = ubo;
+        self.last_sampling = sampling;
+        self.queue
+            .write_buffer(&self.camera_buf, 0, bytemuck::bytes_of(&ubo));
+        for buf in [&self.scene_buf, &self.right_scene_buf] {
+            self.queue.write_buffer(buf, SamplingUbo::OFFSET, bytemuck::bytes_of(&sampling));
+        }
+        if reprojected {
+            self.write_display_ubo();
+        }
+    }
+
+    /// Multiplies exposure by `factor` within `EXPOSURE_RANGE_EV`. Only the
+    /// display changes, so nothing is retraced.
+    pub fn scale_exposure(&mut self, factor: f32) {
+        let (min_ev, max_ev) = EXPOSURE_RANGE_EV;
+        let ev = (self.exposure * factor).log2().clamp(min_ev, max_ev);
+        self.exposure = ev.exp2();
+        self.write_display_ubo();
+        log::info!("exposure: {ev:+.0} EV");
+    }
+
+    /// The part of the surface the trace is shown in; see `active_region`.
+    fn active_region(&self) -> [u32; 4] {
+        active_region(self.camera_ctrl.aspect_lock, self.config.width, self.config.height)
+    }
+
+    fn write_display_ubo(&self) {
+        let blit = &self.blit;
+        let proj = Mat4::from_cols_array_2d(&self.last_ubo.proj_inv).inverse();
+        let (vignette, aberration) = blit.lens.strengths();
+        let ubo = DisplayUbo {
+            exposure: self.exposure,
+            gamma: blit.gamma,
+            encoding: blit.encoding as u32,
+            crosshair: blit.crosshair as u32,
+            split: self.split as u32,
+            sharpen: blit.sharpen,
+            vignette,
+            aberration,
+            depth_proj: [proj.z_axis.z, proj.w_axis.z, proj.z_axis.w, proj.w_axis.w],
+            region: self.active_region().map(|c| c as f32),
+        };
+        self.queue.write_buffer(&blit.display_buf, 0, bytemuck::bytes_of(&ubo));
+    }
+
+    /// The current settings, for a screenshot sidecar.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            camera: self.camera_ctrl.pose(),
+            exposure: self.exposure,
+            resolution_scale: self.resolution_scale,
+            scene: self.scene,
+            holes: self.companions.holes(),
+            seed: Some(self.seed),
+        }
+    }
+
+    /// Restores settings saved by `snapshot`; the next frame retraces.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.camera_ctrl.set_pose(snapshot.camera);
+        let (min_ev, max_ev) = EXPOSURE_RANGE_EV;
+        self.exposure = snapshot.exposure.log2().clamp(min_ev, max_ev).exp2();
+        self.write_display_ubo();
+        let scale = snapshot.resolution_scale.clamp(0.25, 1.0);
+        if scale != self.resolution_scale {
+            self.resolution_scale = scale;
+            self.recreate_storage();
+        }
+        self.scene = snapshot.scene;
+        self.companions = Companions::from_holes(&snapshot.holes);
+        if let Some(seed) = snapshot.seed {
+            self.seed = seed;
+        }
+    }
+
+    pub fn toggle_bloom(&mut self) {
+        self.bloom.toggle(&self.queue);
+    }
+
+    /// Switching restarts accumulation, since the samples differ.
+    pub fn toggle_jitter(&mut self) {
+        self.jitter = !self.jitter;
+        log::info!("anti-aliasing: {}", if self.jitter { "on" } else { "off" });
+    }
+
+    /// Stops or resumes camera uploads. Unfreezing jumps to wherever the
+    /// camera has moved in the meantime.
+    pub fn toggle_camera_freeze(&mut self) {
+        self.camera_frozen = !self.camera_frozen;
+        log::info!("camera: {}", if self.camera_frozen { "frozen" } else { "live" });
+    }
+
+    pub fn cycle_disk_ramp(&mut self) {
+        self.disk_ramp.cycle(&self.queue);
+        self.dirty = true;
+    }
+
+    pub fn toggle_split(&mut self) {
+        self.split = !self.split;
+        self.write_display_ubo();
+        if self.split {
+            log::info!("split screen: right half changes {}", COMPARISONS[self.comparison]);
+        } else {
+            log::info!("split screen: off");
+        }
+    }
+
+    pub fn cycle_sky(&mut self) {
+        self.scene.cycle_sky(self.has_skybox);
+    }
+
+    /// Picks the next parameter the split screen's right half changes.
+    pub fn cycle_comparison(&mut self) {
+        self.comparison = (self.comparison + 1) % COMPARISONS.len();
+        log::info!("split screen: right half changes {}", COMPARISONS[self.comparison]);
+    }
+
+    pub fn toggle_crosshair(&mut self) {
+        self.blit.crosshair = !self.blit.crosshair;
+        self.write_display_ubo();
+    }
+
+    /// Steps through `SHARPEN_LEVELS`; worth turning up at low resolution
+    /// scales, where the upscaled trace looks soft.
+    pub fn cycle_sharpen(&mut self) {
+        let blit = &mut self.blit;
+        let next = SHARPEN_LEVELS
+            .iter()
+            .position(|&s| s == blit.sharpen)
+            .map_or(0, |i| (i + 1) % SHARPEN_LEVELS.len());
+        blit.sharpen = SHARPEN_LEVELS[next];
+        log::info!("sharpen: {}", blit.sharpen);
+        self.write_display_ubo();
+    }
+
+    pub fn toggle_lens_effects(&mut self) {
+        let lens = &mut self.blit.lens;
+        lens.enabled = !lens.enabled;
+        log::info!("lens effects: {}", if lens.enabled { "on" } else { "off" });
+        self.write_display_ubo();
+    }
+
+    /// Steps the vignette through `VIGNETTE_LEVELS`, turning the lens
+    /// effects on so the change shows.
+    pub fn cycle_vignette(&mut self) {
+        let lens = &mut self.blit.lens;
+        let next = VIGNETTE_LEVELS
+            .iter()
+            .position(|&v| v == lens.vignette)
+            .map_or(0, |i| (i + 1) % VIGNETTE_LEVELS.len());
+        lens.vignette = VIGNETTE_LEVELS[next];
+        lens.enabled = true;
+        log::info!("vignette: {}", lens.vignette);
+        self.write_display_ubo();
+    }
+
+    /// Like `cycle_vignette`, for the chromatic aberration.
+    pub fn cycle_aberration(&mut self) {
+        let lens = &mut self.blit.lens;
+        let next = ABERRATION_LEVELS
+            .iter()
+            .position(|&a| a == lens.aberration)
+            .map_or(0, |i| (i + 1) % ABERRATION_LEVELS.len());
+        lens.aberration = ABERRATION_LEVELS[next];
+        lens.enabled = true;
+        log::info!("chromatic aberration: {}", lens.aberration);
+        self.write_display_ubo();
+    }
+
+    /// Steps the camera's aspect lock and resizes the trace to the new
+    /// letterboxed region.
+    pub fn cycle_aspect_lock(&mut self) {
+        self.camera_ctrl.cycle_aspect_lock();
+        self.recreate_storage();
+        self.write_display_ubo();
+    }
+
+    pub fn toggle_gizmo(&mut self) {
+        self.gizmo.visible = !self.gizmo.visible;
+    }
+
+    pub fn toggle_debug_geometry(&mut self) {
+        let debug_geometry = &mut self.debug_geometry;
+        debug_geometry.visible = !debug_geometry.visible;
+        log::info!("debug geometry: {}", if debug_geometry.visible { "on" } else { "off" });
+    }
+
+    pub fn toggle_hud(&mut self) {
+        self.hud.visible = !self.hud.visible;
+    }
+
+    /// Refreshes the HUD text from `stats`; call once per presented frame.
+    pub fn update_hud(&mut self, stats: &FrameStats) {
+        if self.hud.visible {
+            let trace_size = (self.storage_tex().width(), self.storage_tex().height());
+            self.hud.update(&self.queue, stats, (self.config.width, self.config.height), trace_size);
+        }
+    }
+
+    /// Uploads `scene` if it changed since the last frame; any change
+    /// invalidates the traced image.
+    fn update_scene_buffer(&mut self) {
+        let right = if self.split { self.scene.compared(self.comparison) } else { self.scene };
+        if self.scene == self.last_scene && right == self.last_right_scene {
+            return;
+        }
+        self.last_scene = self.scene;
+        self.last_right_scene = right;
+        self.queue
+            .write_buffer(&self.scene_buf, 0, bytemuck::bytes_of(&self.scene));
+        self.queue
+            .write_buffer(&self.right_scene_buf, 0, bytemuck::bytes_of(&right));
+        self.dirty = true;
+    }
+
+    fn update_companions_buffer(&mut self) {
+        if self.companions == self.last_companions {
+            return;
+        }
+        self.last_companions = self.companions;
+        self.queue
+            .write_buffer(&self.companions_buf, 0, bytemuck::bytes_of(&self.companions));
+        self.dirty = true;
+    }
+
+    /// The `storage` texture the last trace wrote.
+    fn storage_tex(&self) -> &wgpu::Texture {
+        &self.storage[self.front].0
+    }
+
+    fn accumulating(&self) -> bool {
+        self.frame_index < self.max_accum
+    }
+
+    pub fn needs_redraw(&self) -> bool {
+        !self.paused
+            && (self.dirty
+                || self.animated
+                || self.accumulating()
+                || self.camera_ctrl.is_moving()
+                || self.resize_pending.is_some())
+    }
+
+    /// Copies the last traced frame back to the CPU as linear float RGBA,
+    /// row by row with the copy alignment padding removed.
+    fn read_storage_texels(&self) -> (u32, u32, Vec<[f32; 4]>) {
+        let (width, height) = (self.storage_tex().width(), self.storage_tex().height());
+        (width, height, self.read_storage_region(UVec2::ZERO, UVec2::new(width, height)))
+    }
+
+    /// Like `read_storage_texels`, for the `size` rectangle at `origin`.
+    fn read_storage_region(&self, origin: UVec2, size: UVec2) -> Vec<[f32; 4]> {
+        let (width, height) = (size.x, size.y);
+        let texel_size = self.storage_format.block_copy_size(None).expect("uncompressed format");
+        let unpadded_bytes_per_row = width * texel_size;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let readback_buf = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback_buf"),
+            size: padded_bytes_per_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("readback_encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: self.storage_tex(),
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: origin.x, y: origin.y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &readback_buf,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = readback_buf.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |res| {
+            let _ = tx.send(res);
+        });
+        self.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .expect("poll");
+        rx.recv().expect("map callback").expect("map readback_buf");
+
+        // Strip the per-row padding required by the copy alignment.
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks_exact(padded_bytes_per_row as usize) {
+                let row = &row[..unpadded_bytes_per_row as usize];
+                match self.storage_format {
+                    wgpu::TextureFormat::Rgba8Unorm => {
+                        let row = bytemuck::cast_slice::<u8, [u8; 4]>(row);
+                        texels.extend(row.iter().map(|texel| texel.map(|c| c as f32 / 255.0)));
+                    }
+                    wgpu::TextureFormat::Rgba32Float => {
+                        texels.extend(row.chunks_exact(16).map(bytemuck::pod_read_unaligned::<[f32; 4]>));
+                    }
+                    _ => {
+                        let row = bytemuck::cast_slice::<u8, [f16; 4]>(row);
+                        texels.extend(row.iter().map(|texel| texel.map(f16::to_f32)));
+                    }
+                }
+            }
+        }
+        readback_buf.unmap();
+        texels
+    }
+
+    /// The trace texel under `cursor` (surface pixels), accounting for the
+    /// letterbox and the resolution scale.
+    fn trace_pixel(&self, cursor: Vec2) -> UVec2 {
+        let [x, y, width, height] = self.active_region().map(|c| c as f32);
+        let scale = Vec2::new(
+            self.storage_tex().width() as f32 / width,
+            self.storage_tex().height() as f32 / height,
+        );
+        ((cursor - Vec2::new(x, y)).max(Vec2::ZERO) * scale).as_uvec2().min(UVec2::new(
+            self.storage_tex().width() - 1,
+            self.storage_tex().height() - 1,
+        ))
+    }
+
+    /// Logs the traced color under `cursor`, as linear radiance and as the
+    /// 8-bit value a screenshot would hold, for debugging shading.
+    pub fn probe_pixel(&self, cursor: Vec2) {
+        let pixel = self.trace_pixel(cursor);
+        let [r, g, b, a] = self.read_storage_region(pixel, UVec2::ONE)[0];
+        let [r8, g8, b8, _] = hdr_to_rgba8([r, g, b, a], self.exposure);
+        log::info!(
+            "pixel ({}, {}): linear ({r:.4}, {g:.4}, {b:.4}, {a:.2}), displayed #{r8:02x}{g8:02x}{b8:02x}",
+            pixel.x,
+            pixel.y
+        );
+    }
+
+    /// Copies the last traced frame back to the CPU as tightly packed RGBA8,
+    /// tonemapped and sRGB-encoded the same way the blit presents it.
+    pub fn read_storage_rgba8(&self) -> FrameCapture {
+        let (width, height, texels) = self.read_storage_texels();
+        let rgba = texels
+            .into_iter()
+            .flat_map(|texel| hdr_to_rgba8(texel, self.exposure))
+            .collect();
+        FrameCapture { width, height, rgba }
+    }
+
+    /// The last traced frame as scene-linear radiance, before exposure and
+    /// tonemapping, for HDR export.
+    pub fn read_storage_hdr(&self) -> HdrCapture {
+        let (width, height, texels) = self.read_storage_texels();
+        HdrCapture { width, height, texels }
+    }
+
+    /// Records one trace dispatch into the back `storage` texture and the
+    /// accumulation history, and makes that texture the front one.
+    fn encode_trace(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let back = 1 - self.front;
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("trace_compute"),
+            timestamp_writes: self.timer.as_ref().map(GpuTimer::compute_writes),
+        });
+        cpass.set_pipeline(&self.compute_pipeline);
+        cpass.set_bind_group(0, &self.compute_bgs[back][(self.frame_index % 2) as usize], &[]);
+        cpass.set_bind_group(1, &self.background_bg, &[]);
+        cpass.set_bind_group(2, &self.disk_ramp.bg, &[]);
+        // The grid comes from the texture itself, never the surface, so a
+        // scaled trace covers every texel exactly once.
+        let (width, height) = (self.storage[back].0.width(), self.storage[back].0.height());
+        debug_assert_eq!(
+            [width as f32, height as f32],
+            self.last_ubo.params[..2],
+            "camera UBO was uploaded for a different trace size"
+        );
+        let wg_x = width.div_ceil(self.workgroup.0);
+        let wg_y = height.div_ceil(self.workgroup.1);
+        cpass.dispatch_workgroups(wg_x, wg_y, 1);
+        self.front = back;
+        self.front_view_proj = self.last_ubo.view_proj();
+        self.dirty = false;
+        self.frame_index += 1;
+    }
+
+    /// The world-space disk point seen at `cursor` (surface pixels), or
+    /// `None` if the ray there ends anywhere else. Traces one extra frame
+    /// with the pick request set and waits for the answer.
+    pub fn pick(&mut self, cursor: Vec2) -> Option<Vec3> {
+        let pixel = self.trace_pixel(cursor);
+        let request = PickBuffer { pixel: pixel.to_array(), requested: 1, ..PickBuffer::zeroed() };
+        self.queue.write_buffer(&self.pick_buf, 0, bytemuck::bytes_of(&request));
+        self.update_scene_buffer();
+        self.update_companions_buffer();
+        self.update_camera_buffer(self.last_time);
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("pick_encoder"),
+            });
+        self.encode_trace(&mut encoder);
+        encoder.copy_buffer_to_buffer(&self.pick_buf, 0, &self.pick_readback, 0, None);
+        self.queue.submit(Some(encoder.finish()));
+        self.queue.write_buffer(&self.pick_buf, 0, bytemuck::bytes_of(&PickBuffer::zeroed()));
+
+        let slice = self.pick_readback.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |res| {
+            let _ = tx.send(res);
+        });
+        let _ = self.device.poll(wgpu::PollType::wait_indefinitely());
+        if let Err(e) = rx.recv().expect("map callback") {
+            log::warn!("pick readback failed: {e}");
+            return None;
+        }
+        let result: PickBuffer = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        self.pick_readback.unmap();
+        (result.hit == HIT_DISK).then(|| Vec3::from_array(result.position))
+    }
+
+    /// Traces `frames` frames back to back and returns the trace size and
+    /// the mean time per frame, timed on the CPU from submit until the GPU
+    /// is idle. One untimed frame first absorbs shader compilation.
+    pub fn bench_trace(&mut self, frames: u32) -> ((u32, u32), Duration) {
+        self.render_offscreen(0.0);
+        let _ = self.device.poll(wgpu::PollType::wait_indefinitely());
+        let mut total = Duration::ZERO;
+        for _ in 0..frames {
+            let start = Instant::now();
+            self.render_offscreen(0.0);
+            let _ = self.device.poll(wgpu::PollType::wait_indefinitely());
+            total += start.elapsed();
+        }
+        let size = (self.storage_tex().width(), self.storage_tex().height());
+        (size, total / frames.max(1))
+    }
+
+    /// Traces a single frame without presenting it; pair with `read_storage_rgba8`.
+    pub fn render_offscreen(&mut self, time: f32) {
+        self.update_scene_buffer();
+        self.update_companions_buffer();
+        self.update_camera_buffer(time);
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("offscreen_encoder"),
+            });
+        self.encode_trace(&mut encoder);
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    pub fn render(&mut self, surface: &wgpu::Surface<'_>, time: f32) -> Result<(), wgpu::SurfaceError> {
+        self.set_output_size(self.size);
+        self.apply_pending_resize();
+        self.update_scene_buffer();
+        self.update_companions_buffer();
+        self.update_camera_buffer(time);
+
+        let frame = surface.get_current_texture()?;
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("surface_view"),
+            ..Default::default()
+        });
+        let started = Instant::now();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("frame_encoder"),
+            });
+        let traced = self.encode_frame(&mut encoder, &view);
+        self.submit_frame(encoder, traced, started);
+        frame.present();
+        Ok(())
+    }
+
+    /// Like `render`, but into the texture `viewport_view` returns instead
+    /// of a surface, for hosts that show the image inside their own UI and
+    /// build the renderer without a surface. The host hands the view to its
+    /// UI renderer and does so again whenever `resize_viewport` recreates
+    /// it. This crate has no egui integration of its own: with egui-wgpu,
+    /// the host registers the view with `register_native_texture` and
+    /// updates it with `update_egui_texture_from_wgpu_texture`, which keeps
+    /// egui out of this crate's dependencies.
+    ///
+    /// ```no_run
+    /// # use blackhole_wgpu::{Config, GpuState};
+    /// # fn show(_view: &wgpu::TextureView) {}
+    /// # fn frame(adapter: &wgpu::Adapter, device: &wgpu::Device, queue: &wgpu::Queue, (width, height): (u32, u32), time: f32) {
+    /// let size = winit::dpi::PhysicalSize::new(width, height);
+    /// let mut renderer = GpuState::with_device(adapter, device, queue, None, size, &Config::default());
+    /// // per frame, with the panel's size in physical pixels:
+    /// if renderer.resize_viewport(width, height) {
+    ///     show(renderer.viewport_view().unwrap());
+    /// }
+    /// renderer.render_to_viewport(time);
+    /// # }
+    /// ```
+    pub fn render_to_viewport(&mut self, time: f32) {
+        let (size, view) = self
+            .viewport
+            .as_ref()
+            .map(|(tex, view)| (PhysicalSize::new(tex.width(), tex.height()), view.clone()))
+            .expect("render_to_viewport() needs resize_viewport() first");
+        self.set_output_size(size);
+        self.apply_pending_resize();
+        self.update_scene_buffer();
+        self.update_companions_buffer();
+        self.update_camera_buffer(time);
+        let started = Instant::now();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("viewport_encoder"),
+            });
+        let traced = self.encode_frame(&mut encoder, &view);
+        self.submit_frame(encoder, traced, started);
+    }
+
+    /// Sizes the offscreen viewport `render_to_viewport` draws into, e.g.
+    /// to an egui panel's size in physical pixels, whatever size the window
+    /// is. Returns true when the texture was recreated, after which
+    /// whatever holds the old `viewport_view` has to take the new one. The
+    /// trace follows on the next frames, as it does a window resize.
+    pub fn resize_viewport(&mut self, width: u32, height: u32) -> bool {
+        let current = self.viewport.as_ref().map(|(tex, _)| (tex.width(), tex.height()));
+        if width == 0 || height == 0 || current == Some((width, height)) {
+            return false;
+        }
+        log::debug!("resizing viewport to {width}x{height}");
+        self.viewport = Some(create_viewport_target(&self.device, self.config.format, width, height));
+        true
+    }
+
+    /// The offscreen viewport, in `VIEWPORT_FORMAT` without a surface and
+    /// the surface's format with one; `None` until `resize_viewport` is
+    /// called.
+    pub fn viewport_view(&self) -> Option<&wgpu::TextureView> {
+        self.viewport.as_ref().map(|(_, view)| view)
+    }
+
+    /// Records the trace (when due), bloom and the blit with its overlays
+    /// into `view`. Returns whether the trace ran.
+    fn encode_frame(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) -> bool {
+        // compute
+        let traced = !self.paused && (self.dirty || self.accumulating());
+        if traced {
+            self.encode_trace(encoder);
+        }
+
+        if traced || self.bloom.stale {
+            self.bloom.encode(encoder, self.front);
+        }
+
+        if self.gizmo.visible {
+            self.gizmo.update(&self.queue, Mat4::from_cols_array_2d(&self.last_ubo.view_inv));
+        }
+        if self.debug_geometry.visible {
+            let view_inv = Mat4::from_cols_array_2d(&self.last_ubo.view_inv);
+            let proj_inv = Mat4::from_cols_array_2d(&self.last_ubo.proj_inv);
+            self.debug_geometry.update(&self.queue, (view_inv, proj_inv), &self.scene, &self.companions);
+        }
+
+        // blit
+        {
+            let blit = &self.blit;
+            let clear = if self.scene.sky_mode == SKY_COLOR {
+                blit.clear_color(self.scene.sky_color, self.exposure)
+            } else {
+                wgpu::Color::BLACK
+            };
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("blit_render"),
+                color_attachments: &[Some(match blit.msaa_view.as_ref() {
+                    // Only the resolved surface image needs to be kept.
+                    Some(msaa_view) => wgpu::RenderPassColorAttachment {
+                        view: msaa_view,
+                        resolve_target: Some(view),
+                        depth_slice: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(clear),
+                            store: wgpu::StoreOp::Discard,
+                        },
+                    },
+                    None => wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        depth_slice: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(clear),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &blit.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: self.timer.as_ref().map(GpuTimer::render_writes),
+            });
+            rpass.set_pipeline(&blit.render_pipeline);
+            rpass.set_bind_group(0, &blit.render_bgs[self.front], &[]);
+            rpass.set_bind_group(1, &blit.display_bg, &[]);
+            rpass.draw(0..3, 0..1);
+            if self.debug_geometry.visible {
+                // Projected like the trace, so kept inside the letterbox.
+                let [x, y, width, height] = self.active_region().map(|c| c as f32);
+                rpass.set_viewport(x, y, width, height, 0.0, 1.0);
+                self.debug_geometry.draw(&mut rpass);
+                let (width, height) = (self.config.width as f32, self.config.height as f32);
+                rpass.set_viewport(0.0, 0.0, width, height, 0.0, 1.0);
+            }
+            if self.hud.visible {
+                self.hud.draw(&mut rpass);
+            }
+            if self.gizmo.visible {
+                self.gizmo.draw(&mut rpass, (self.config.width, self.config.height));
+            }
+        }
+
+        traced
+    }
+
+    /// Submits a frame whose encoding began at `started` and feeds its
+    /// trace time to adaptive resolution: the compute pass's GPU time where
+    /// timestamp queries are supported, otherwise the time until the GPU
+    /// has finished the frame. Neither counts vsync or frame cap waits.
+    fn submit_frame(&mut self, mut encoder: wgpu::CommandEncoder, traced: bool, started: Instant) {
+        // Only frames that ran the trace have both pass timings.
+        let resolved = match self.timer.as_mut() {
+            Some(timer) if traced => timer.resolve(&mut encoder),
+            _ => false,
+        };
+        self.queue.submit(Some(encoder.finish()));
+        let trace_time = match self.timer.as_mut() {
+            Some(timer) => {
+                if resolved {
+                    timer.start_readback();
+                }
+                timer.poll(&self.device)
+            }
+            // Without a GPU clock, wait for the frame to finish so the trace
+            // counts; that gives up CPU/GPU overlap, so only with a budget.
+            None if traced && self.frame_budget.is_some() => {
+                let _ = self.device.poll(wgpu::PollType::wait_indefinitely());
+                Some(started.elapsed())
+            }
+            None => None,
+        };
+        if let Some(trace_time) = trace_time {
+            self.adapt_resolution(trace_time);
+        }
+    }
+}
+
+fn create_render_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    (storage_view, trace_depth_view): (&wgpu::TextureView, &wgpu::TextureView),
+    bloom_view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("render_bg"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(storage_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(bloom_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(trace_depth_view),
+            },
+        ],
+    })
+}
+
+fn create_storage_textures(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> [(wgpu::Texture, wgpu::TextureView); 2] {
+    ["storage_tex_0", "storage_tex_1"]
+        .map(|label| create_storage_texture(device, label, width, height, format))
+}
+
+fn create_accum_textures(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> [(wgpu::Texture, wgpu::TextureView); 2] {
+    ["accum_tex_0", "accum_tex_1"]
+        .map(|label| create_storage_texture(device, label, width, height, format))
+}
+
+/// The `requested` format if the adapter can write it from a shader and
+/// `device` can filter it, both of which the trace, bloom and blit need.
+/// Otherwise `STORAGE_FORMAT` if it can be bound as a write-only storage
+/// texture, or failing that the 8-bit fallback, which clips highlights and
+/// bands but runs.
+fn pick_storage_format(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    requested: Option<StorageFormat>,
+) -> wgpu::TextureFormat {
+    if let Some(requested) = requested {
+        let format = match requested {
+            StorageFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8Unorm,
+            StorageFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+            StorageFormat::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+        };
+        let usages = adapter.get_texture_format_features(format).allowed_usages;
+        let filterable = matches!(
+            format.sample_type(None, Some(device.features())),
+            Some(wgpu::TextureSampleType::Float { filterable: true })
+        );
+        if usages.contains(wgpu::TextureUsages::STORAGE_BINDING) && filterable {
+            log::info!("storage format: {format:?}");
+            return format;
+        }
+        log::warn!("{format:?} can't be written and filtered as a storage texture here; using the default");
+    }
+    let usages = adapter.get_texture_format_features(STORAGE_FORMAT).allowed_usages;
+    if usages.contains(wgpu::TextureUsages::STORAGE_BINDING) {
+        return STORAGE_FORMAT;
+    }
+    log::warn!(
+        "{STORAGE_FORMAT:?} can't be used as a storage texture on this adapter; \
+         tracing into {FALLBACK_STORAGE_FORMAT:?}, so highlights clip and gradients may band"
+    );
+    FALLBACK_STORAGE_FORMAT
+}
+
+/// Rewrites the storage texel format in a shader written for `STORAGE_FORMAT`.
+pub(crate) fn storage_wgsl(source: &str, format: wgpu::TextureFormat) -> std::borrow::Cow<'_, str> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm => source.replace("rgba16float", "rgba8unorm").into(),
+        wgpu::TextureFormat::Rgba32Float => source.replace("rgba16float", "rgba32float").into(),
+        _ => source.into(),
+    }
+}
+
+fn create_compute_bind_groups(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    // both of the trace's color outputs, and its depth output
+    (storage, depth_view): (&[(wgpu::Texture, wgpu::TextureView); 2], &wgpu::TextureView),
+    camera_buf: &wgpu::Buffer,
+    // the left and right scene uniforms, the companions storage buffer and
+    // the pick buffer
+    [scene_buf, right_scene_buf, companions_buf, pick_buf]: [&wgpu::Buffer; 4],
+    accum: &[(wgpu::Texture, wgpu::TextureView); 2],
+) -> [[wgpu::BindGroup; 2]; 2] {
+    const LABELS: [[&str; 2]; 2] = [["compute_bg_0_0", "compute_bg_0_1"], ["compute_bg_1_0", "compute_bg_1_1"]];
+    [0, 1].map(|output| [0, 1].map(|read| {
+        let write = 1 - read;
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(LABELS[output][read]),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&storage[output].1),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: camera_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&accum[read].1),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&accum[write].1),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: scene_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: companions_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: right_scene_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: pick_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(depth_view),
+                },
+                // The other output holds the frame before.
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: wgpu::BindingResource::TextureView(&storage[1 - output].1),
+                },
+            ],
+        })
+    }))
+}
+
+/// The compute shader the trace runs: trace.wgsl's `main`, or a variant
+/// named in the config.
+struct TraceShader {
+    label: String,
+    source: String,
+    entry_point: String,
+    constants: Vec<(String, f64)>,
+    /// The tile size to dispatch at when the entry point doesn't take it
+    /// from the `WORKGROUP_X` and `WORKGROUP_Y` overrides, which rules out
+    /// tuning it.
+    fixed_workgroup: Option<(u32, u32)>,
+}
+
+impl TraceShader {
+    fn builtin(storage_format: wgpu::TextureFormat) -> Self {
+        Self {
+            label: "trace.wgsl".to_owned(),
+            source: storage_wgsl(include_str!("../shaders/trace.wgsl"), storage_format).into_owned(),
+            entry_point: "main".to_owned(),
+            constants: Vec::new(),
+            fixed_workgroup: None,
+        }
+    }
+
+    /// The config's `trace_shader`, `trace_entry_point` and
+    /// `trace_constants`, checked by building a pipeline from them. One that
+    /// can't be read, lacks the entry point or doesn't compile against
+    /// `layout` falls back to the built-in trace with a warning.
+    fn from_config(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        startup: &Config,
+        storage_format: wgpu::TextureFormat,
+    ) -> Self {
+        let builtin = Self::builtin(storage_format);
+        if startup.trace_shader.is_none() && startup.trace_entry_point.is_none() && startup.trace_constants.is_empty() {
+            return builtin;
+        }
+        let (label, source) = match startup.trace_shader.as_deref() {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(source) => (path.display().to_string(), storage_wgsl(&source, storage_format).into_owned()),
+                Err(e) => {
+                    log::warn!("trace shader {}: {e}; using trace.wgsl", path.display());
+                    return builtin;
+                }
+            },
+            None => (builtin.label.clone(), builtin.source.clone()),
+        };
+        let entry_point = startup.trace_entry_point.clone().unwrap_or_else(|| builtin.entry_point.clone());
+        let fixed_workgroup = fixed_workgroup(&source, &entry_point);
+        if let Some((x, y)) = fixed_workgroup {
+            log::warn!(
+                "trace shader {label} ({entry_point}) doesn't size its workgroups from WORKGROUP_X/WORKGROUP_Y; dispatching at {x}x{y} untuned"
+            );
+        }
+        let shader = Self {
+            label,
+            source,
+            entry_point,
+            constants: startup.trace_constants.iter().map(|(name, &value)| (name.clone(), value)).collect(),
+            fixed_workgroup,
+        };
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        create_trace_pipeline(device, layout, &shader, (8, 8));
+        match pollster::block_on(device.pop_error_scope()) {
+            None => {
+                log::info!("trace shader: {} ({})", shader.label, shader.entry_point);
+                shader
+            }
+            Some(e) => {
+                log::warn!("trace shader {} ({}) unusable: {e}; using trace.wgsl", shader.label, shader.entry_point);
+                builtin
+            }
+        }
+    }
+}
+
+/// Builds the trace pipeline for one tile size, passed as the shader's
+/// `WORKGROUP_X` and `WORKGROUP_Y` override constants.
+fn create_trace_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &TraceShader,
+    workgroup: (u32, u32),
+) -> wgpu::ComputePipeline {
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(&shader.label),
+        source: wgpu::ShaderSource::Wgsl(shader.source.as_str().into()),
+    });
+    let tile = [("WORKGROUP_X", workgroup.0 as f64), ("WORKGROUP_Y", workgroup.1 as f64)];
+    let constants: Vec<(&str, f64)> = shader
+        .constants
+        .iter()
+        .map(|(name, value)| (name.as_str(), *value))
+        .chain(tile)
+        .collect();
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("trace_compute"),
+        layout: Some(layout),
+        module: &module,
+        entry_point: Some(&shader.entry_point),
+        cache: None,
+        compilation_options: wgpu::PipelineCompilationOptions { constants: &constants, ..Default::default() },
+    })
+}
+
+/// The x and y tile size `entry_point` in `source` runs at, or `None` when
+/// both come from the `WORKGROUP_X` and `WORKGROUP_Y` overrides and can be
+/// tuned. Those overrides are set to 8 when not tuning, and a size from any
+/// other override is taken to be 8 as well. A shader that doesn't parse gives
+/// `None` and is rejected when its pipeline is built.
+fn fixed_workgroup(source: &str, entry_point: &str) -> Option<(u32, u32)> {
+    use wgpu::naga;
+    let module = naga::front::wgsl::parse_str(source).ok()?;
+    let ep = module.entry_points.iter().find(|ep| ep.name == entry_point)?;
+    let overrides = ep.workgroup_size_overrides.unwrap_or_default();
+    // Per axis: whether it's the tuning override, and the size it runs at.
+    let axis = |i: usize, name: &str| match overrides[i].map(|expr| &module.global_expressions[expr]) {
+        None => (false, ep.workgroup_size[i]),
+        Some(&naga::Expression::Override(h)) if module.overrides[h].name.as_deref() == Some(name) => (true, 8),
+        Some(_) => (false, 8),
+    };
+    match (axis(0, "WORKGROUP_X"), axis(1, "WORKGROUP_Y")) {
+        ((true, _), (true, _)) => None,
+        ((_, x), (_, y)) => Some((x, y)),
+    }
+}
+
+/// Times one trace of a `WORKGROUP_BENCH_TILE` square with each of
+/// `WORKGROUP_CANDIDATES` the device supports and returns the fastest, with
+/// its pipeline. Each size is run twice and only the second run counts, so
+/// shader compilation and warm-up don't skew the result. The frames it
+/// writes are overwritten by the first real trace.
+fn pick_workgroup_size(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::PipelineLayout,
+    shader: &TraceShader,
+    bind_groups: [&wgpu::BindGroup; 3],
+    trace_size: (u32, u32),
+) -> ((u32, u32), wgpu::ComputePipeline) {
+    let limits = device.limits();
+    let tile = (
+        trace_size.0.min(WORKGROUP_BENCH_TILE),
+        trace_size.1.min(WORKGROUP_BENCH_TILE),
+    );
+    let mut best: Option<(Duration, (u32, u32), wgpu::ComputePipeline)> = None;
+    for (x, y) in WORKGROUP_CANDIDATES {
+        if x > limits.max_compute_workgroup_size_x
+            || y > limits.max_compute_workgroup_size_y
+            || x * y > limits.max_compute_invocations_per_workgroup
+        {
+            continue;
+        }
+        let pipeline = create_trace_pipeline(device, layout, shader, (x, y));
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..2 {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("workgroup_bench"),
+            });
+            {
+                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("workgroup_bench"),
+                    timestamp_writes: None,
+                });
+                cpass.set_pipeline(&pipeline);
+                for (index, bind_group) in bind_groups.iter().enumerate() {
+                    cpass.set_bind_group(index as u32, *bind_group, &[]);
+                }
+                cpass.dispatch_workgroups(tile.0.div_ceil(x), tile.1.div_ceil(y), 1);
+            }
+            let start = Instant::now();
+            queue.submit(Some(encoder.finish()));
+            let _ = device.poll(wgpu::PollType::wait_indefinitely());
+            elapsed = start.elapsed();
+        }
+        if best.as_ref().is_none_or(|(fastest, ..)| elapsed < *fastest) {
+            best = Some((elapsed, (x, y), pipeline));
+        }
+    }
+    // 8x8 is within WebGPU's minimum limits, so there's always a candidate.
+    let (elapsed, workgroup, pipeline) = best.expect("8x8 workgroups are always supported");
+    log::info!(
+        "trace workgroup: {}x{} ({:.2} ms for a {}x{} tile)",
+        workgroup.0,
+        workgroup.1,
+        elapsed.as_secs_f64() * 1000.0,
+        tile.0,
+        tile.1
+    );
+    (workgroup, pipeline)
+}
+
+/// Trace resolution for a surface of `width`x`height` at `scale`, with no
+/// side over `max_dim`.
+fn scaled_size(width: u32, height: u32, scale: f32, max_dim: u32) -> (u32, u32) {
+    // Past the device's texture limit, shrink both sides to keep the aspect.
+    let longest = width.max(height) as f32 * scale;
+    let scale = if longest > max_dim as f32 {
+        log::warn!("trace clamped to the device's {max_dim}px texture limit");
+        scale * max_dim as f32 / longest
+    } else {
+        scale
+    };
+    let scale_dim = |d: u32| ((d as f32 * scale).round() as u32).clamp(1, max_dim);
+    (scale_dim(width), scale_dim(height))
+}
@@ -0,0 +1,269 @@
+use std::time::{Duration, Instant};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::texture::depth_state;
+
+// Must match COLS/ROWS in hud.wgsl.
+const COLS: usize = 16;
+const ROWS: usize = 3;
+// Code the shader draws as a blank cell.
+const SPACE: u32 = 11;
+// Text is rewritten at most this often so the numbers stay readable.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Rolling window of recent frame times.
+pub struct FrameStats {
+    samples: [f32; 64],
+    next: usize,
+    len: usize,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self { samples: [0.0; 64], next: 0, len: 0 }
+    }
+
+    /// Records the time between two redraws. Gaps of a second or more are
+    /// the loop idling rather than a slow frame and are left out.
+    pub fn record(&mut self, dt: Duration) {
+        if dt >= Duration::from_secs(1) {
+            return;
+        }
+        self.samples[self.next] = dt.as_secs_f32() * 1000.0;
+        self.next = (self.next + 1) % self.samples.len();
+        self.len = (self.len + 1).min(self.samples.len());
+    }
+
+    pub fn average_ms(&self) -> Option<f32> {
+        (self.len > 0).then(|| self.samples[..self.len].iter().sum::<f32>() / self.len as f32)
+    }
+}
+
+/// Decides when adaptive resolution should step the trace scale, from
+/// runs of frames over or well under a target frame time.
+pub(crate) struct FrameBudget {
+    target: Duration,
+    over: u32,
+    under: u32,
+}
+
+impl FrameBudget {
+    // Consecutive frames needed before reacting, so one hitch or one quiet
+    // frame doesn't flip the resolution.
+    const OVER_FRAMES: u32 = 8;
+    const UNDER_FRAMES: u32 = 60;
+    // Below this fraction of the target there is room to trace more pixels.
+    const HEADROOM: f32 = 0.7;
+
+    pub(crate) fn new(target_ms: f32) -> Self {
+        Self { target: Duration::from_secs_f32(target_ms.max(1.0) / 1000.0), over: 0, under: 0 }
+    }
+
+    /// Feeds one frame's trace time; returns -1 to shrink the trace, +1 to
+    /// grow it. Stalls of a second or more are ignored.
+    pub(crate) fn record(&mut self, dt: Duration) -> Option<i32> {
+        if dt >= Duration::from_secs(1) {
+            return None;
+        }
+        if dt > self.target {
+            self.over += 1;
+            self.under = 0;
+        } else if dt < self.target.mul_f32(Self::HEADROOM) {
+            self.under += 1;
+            self.over = 0;
+        } else {
+            self.over = 0;
+            self.under = 0;
+        }
+        if self.over >= Self::OVER_FRAMES {
+            self.over = 0;
+            Some(-1)
+        } else if self.under >= Self::UNDER_FRAMES {
+            self.under = 0;
+            Some(1)
+        } else {
+            None
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct HudUbo {
+    screen: [f32; 2],
+    origin: [f32; 2],
+    scale: f32,
+    _pad: [f32; 3],
+    cells: [[u32; 4]; COLS * ROWS / 4],
+}
+
+fn glyph_code(c: char) -> u32 {
+    match c {
+        '0'..='9' => c as u32 - '0' as u32,
+        '.' => 10,
+        'F' | 'f' => 12,
+        'P' | 'p' => 13,
+        'S' | 's' => 14,
+        'M' | 'm' => 15,
+        'X' | 'x' => 16,
+        ':' => 17,
+        _ => SPACE,
+    }
+}
+
+/// Packs up to `ROWS` lines of `COLS` characters into shader glyph codes.
+fn encode_lines(lines: &[String]) -> [[u32; 4]; COLS * ROWS / 4] {
+    let mut codes = [SPACE; COLS * ROWS];
+    for (row, line) in lines.iter().take(ROWS).enumerate() {
+        for (col, c) in line.chars().take(COLS).enumerate() {
+            codes[row * COLS + col] = glyph_code(c);
+        }
+    }
+    let mut cells = [[0; 4]; COLS * ROWS / 4];
+    for (cell, chunk) in cells.iter_mut().zip(codes.chunks_exact(4)) {
+        cell.copy_from_slice(chunk);
+    }
+    cells
+}
+
+/// FPS / frame-time overlay drawn at the end of the blit pass. The text is
+/// rendered from a tiny bitmap font in the fragment shader, so it costs one
+/// small quad and a uniform upload a few times per second.
+pub(crate) struct HudPass {
+    ubo: HudUbo,
+    buf: wgpu::Buffer,
+    bg: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    last_refresh: Option<Instant>,
+    pub(crate) visible: bool,
+}
+
+impl HudPass {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("hud.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/hud.wgsl").into()),
+        });
+        let ubo = HudUbo {
+            screen: [1.0, 1.0],
+            origin: [8.0, 8.0],
+            scale: 2.0,
+            _pad: [0.0; 3],
+            cells: encode_lines(&[]),
+        };
+        let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("hud_ubo"),
+            contents: bytemuck::bytes_of(&ubo),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("hud_bgl"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("hud_bg"),
+            layout: &bgl,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buf.as_entire_binding(),
+            }],
+        });
+        let pl = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("hud_pl"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("hud_pipeline"),
+            layout: Some(&pl),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: Some("vs"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: Some("fs"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(depth_state(false, wgpu::CompareFunction::Always)),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            ubo,
+            buf,
+            bg,
+            pipeline,
+            last_refresh: None,
+            visible: true,
+        }
+    }
+
+    /// Rewrites the overlay text if it is due for a refresh.
+    pub(crate) fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        stats: &FrameStats,
+        screen: (u32, u32),
+        trace: (u32, u32),
+    ) {
+        let now = Instant::now();
+        let screen = [screen.0 as f32, screen.1 as f32];
+        let due = self.last_refresh.is_none_or(|t| now - t >= REFRESH_INTERVAL);
+        if !due && screen == self.ubo.screen {
+            return;
+        }
+        self.last_refresh = Some(now);
+        let lines = match stats.average_ms() {
+            Some(ms) => [
+                format!("FPS {:.1}", 1000.0 / ms),
+                format!("{ms:.2} MS"),
+                format!("{}X{}", trace.0, trace.1),
+            ],
+            None => [String::new(), String::new(), format!("{}X{}", trace.0, trace.1)],
+        };
+        self.ubo.screen = screen;
+        self.ubo.cells = encode_lines(&lines);
+        queue.write_buffer(&self.buf, 0, bytemuck::bytes_of(&self.ubo));
+    }
+
+    pub(crate) fn draw(&self, rpass: &mut wgpu::RenderPass<'_>) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bg, &[]);
+        rpass.draw(0..6, 0..1);
+    }
+}
@@ -0,0 +1,82 @@
+//! A compute-shader black hole renderer for wgpu.
+//!
+//! [`GpuState`] holds everything between a device and the finished frame:
+//! the geodesic trace, accumulation, bloom and the display blit. It never
+//! creates a window or runs an event loop; the host hands it a surface to
+//! draw to and forwards input to its [`CameraCtrl`]. The binary in
+//! `main.rs` is one such host. Hosts with their own UI, such as an egui
+//! app, build it without a surface and have it draw into a texture sized
+//! by [`GpuState::resize_viewport`] with [`GpuState::render_to_viewport`].
+//! There is no egui feature, so registering that texture with egui-wgpu is
+//! up to the host.
+//!
+//! ```no_run
+//! # use blackhole_wgpu::{Config, GpuState};
+//! # fn run(
+//! #     (adapter, device, queue): (&wgpu::Adapter, &wgpu::Device, &wgpu::Queue),
+//! #     surface: &wgpu::Surface<'_>,
+//! #     size: winit::dpi::PhysicalSize<u32>,
+//! #     (frame_time, time): (std::time::Duration, f32),
+//! # ) -> Result<(), wgpu::SurfaceError> {
+//! let mut renderer = GpuState::with_device(adapter, device, queue, Some(surface), size, &Config::default());
+//! // per frame:
+//! renderer.update(frame_time);
+//! renderer.render(surface, time)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use winit::dpi::PhysicalSize;
+
+mod background;
+mod bloom;
+pub mod camera;
+pub mod config;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
+mod debug_geometry;
+mod gizmo;
+pub mod gpu;
+pub mod hud;
+mod ramp;
+pub mod scene;
+pub mod session;
+pub mod snapshot;
+pub mod texture;
+
+pub use camera::CameraCtrl;
+pub use config::Config;
+pub use gpu::{GpuState, InitError};
+
+/// The per-frame surface of a renderer, for hosts that drive it from their
+/// own loop. The device and queue are the ones it was built with; only the
+/// surface is borrowed per call.
+pub trait Renderer {
+    /// Reconfigures `surface` for a new size in physical pixels; the render
+    /// targets follow over the next frames. Zero-sized (minimized) windows
+    /// are ignored.
+    fn resize(&mut self, surface: &wgpu::Surface<'_>, width: u32, height: u32);
+
+    /// Advances camera motion and other per-frame state by the time since
+    /// the last frame.
+    fn update(&mut self, frame_time: Duration);
+
+    /// Traces if needed and presents one frame at shader time `time`.
+    fn render(&mut self, surface: &wgpu::Surface<'_>, time: f32) -> Result<(), wgpu::SurfaceError>;
+}
+
+impl Renderer for GpuState {
+    fn resize(&mut self, surface: &wgpu::Surface<'_>, width: u32, height: u32) {
+        GpuState::resize(self, surface, PhysicalSize::new(width, height));
+    }
+
+    fn update(&mut self, frame_time: Duration) {
+        GpuState::update(self, frame_time);
+    }
+
+    fn render(&mut self, surface: &wgpu::Surface<'_>, time: f32) -> Result<(), wgpu::SurfaceError> {
+        GpuState::render(self, surface, time)
+    }
+}
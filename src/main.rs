@@ -1,487 +1,365 @@
+use std::borrow::Cow;
 use std::f32::consts::PI;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use bytemuck::{Pod, Zeroable};
-use glam::{Mat4, Vec2, Vec3};
-use wgpu::util::DeviceExt;
+use glam::Vec2;
 
 use winit::application::ApplicationHandler;
 use winit::event::*;
-use winit::event_loop::{ActiveEventLoop, EventLoop};
-use winit::window::{Window, WindowAttributes};
-use winit::dpi::PhysicalSize;
-
-#[repr(C)]
-#[derive(Clone, Copy, Pod, Zeroable)]
-struct CameraUbo {
-    view_inv: [[f32; 4]; 4],
-    proj_inv: [[f32; 4]; 4],
-    params: [f32; 4], // (width, height, time, _pad)
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{Key, ModifiersState, NamedKey, PhysicalKey};
+use winit::window::{Icon, Window, WindowAttributes};
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize};
+
+use blackhole_wgpu::camera::{DEFAULT_FOV_Y_DEG, FOV_STEP_DEG};
+use blackhole_wgpu::config::{Config, HoleConfig, StorageFormat};
+#[cfg(feature = "gamepad")]
+use blackhole_wgpu::gamepad;
+use blackhole_wgpu::hud::FrameStats;
+use blackhole_wgpu::session::Session;
+use blackhole_wgpu::snapshot::Snapshot;
+use blackhole_wgpu::texture::FrameCapture;
+use blackhole_wgpu::{GpuState, InitError};
+
+// ---------- Capture ----------
+/// `prefix_<unix millis>.ext`, so rapid captures don't overwrite each other.
+fn timestamped_path(prefix: &str, ext: &str) -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    format!("{prefix}_{millis}.{ext}")
 }
 
-struct CameraCtrl {
-    yaw: f32,
-    pitch: f32,
-    radius: f32,
-    fov_y: f32,
-    dragging: bool,
-    last_cursor: Option<Vec2>,
+/// Saves `frame` as a PNG with the settings that produced it alongside, for
+/// `--load`.
+fn save_screenshot(frame: &FrameCapture, snapshot: &Snapshot) {
+    let path = timestamped_path("screenshot", "png");
+    match frame.save_png(Path::new(&path)) {
+        Ok(()) => log::info!("saved {}x{} screenshot to {path}", frame.width, frame.height),
+        Err(e) => {
+            log::warn!("failed to save {path}: {e}");
+            return;
+        }
+    }
+    let sidecar = Snapshot::sidecar_path(Path::new(&path));
+    if let Err(e) = snapshot.save(&sidecar) {
+        log::warn!("failed to save {}: {e}", sidecar.display());
+    }
 }
-impl CameraCtrl {
-    fn new() -> Self {
-        Self {
-            yaw: 0.6,
-            pitch: 0.3,
-            radius: 4.0,
-            fov_y: 60.0_f32.to_radians(),
-            dragging: false,
-            last_cursor: None,
+
+fn copy_frame_to_clipboard(clipboard: &mut Option<arboard::Clipboard>, frame: &FrameCapture) {
+    if clipboard.is_none() {
+        match arboard::Clipboard::new() {
+            Ok(cb) => *clipboard = Some(cb),
+            Err(e) => log::warn!("clipboard unavailable: {e}"),
         }
     }
-    fn eye_target_up(&self) -> (Vec3, Vec3, Vec3) {
-        let x = self.radius * self.yaw.cos() * self.pitch.cos();
-        let y = self.radius * self.pitch.sin();
-        let z = self.radius * self.yaw.sin() * self.pitch.cos();
-        (Vec3::new(x, y, z), Vec3::ZERO, Vec3::Y)
+    let copied = clipboard.as_mut().map(|cb| {
+        cb.set_image(arboard::ImageData {
+            width: frame.width as usize,
+            height: frame.height as usize,
+            bytes: Cow::Borrowed(&frame.rgba),
+        })
+    });
+    match copied {
+        Some(Ok(())) => log::info!("copied {}x{} frame to clipboard", frame.width, frame.height),
+        Some(Err(e)) => {
+            log::warn!("clipboard image copy failed: {e}");
+            save_frame_fallback(frame);
+        }
+        None => save_frame_fallback(frame),
     }
 }
 
-struct GpuState {
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-    size: PhysicalSize<u32>,
-
-    // compute output
-    storage_tex: wgpu::Texture,
-    storage_view: wgpu::TextureView,
-    sampler: wgpu::Sampler,
-
-    // camera
-    camera_ctrl: CameraCtrl,
-    camera_buf: wgpu::Buffer,
-
-    // compute
-    compute_bgl: wgpu::BindGroupLayout,
-    compute_bg: wgpu::BindGroup,
-    compute_pipeline: wgpu::ComputePipeline,
-
-    // blit
-    render_bgl: wgpu::BindGroupLayout,
-    render_bg: wgpu::BindGroup,
-    render_pipeline: wgpu::RenderPipeline,
+fn save_frame_fallback(frame: &FrameCapture) {
+    let path = timestamped_path("capture", "png");
+    match frame.save_png(Path::new(&path)) {
+        Ok(()) => log::info!("saved frame to {path} instead"),
+        Err(e) => log::warn!("failed to save {path}: {e}"),
+    }
 }
 
-impl GpuState {
-    async fn new(
-        instance: &wgpu::Instance,
-        window: &Window,
-        surface: &wgpu::Surface<'_>,
-    ) -> Self {
-        let size = window.inner_size();
-
-        // Adapter / device
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
-                compatible_surface: Some(surface),
-            })
-            .await
-            .expect("No GPU adapter");
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::default(),
-                    ..Default::default()
-                }
-            )
-            .await
-            .expect("device");
-
-        // Surface config
-        let caps = surface.get_capabilities(&adapter);
-        let surface_format = caps
-            .formats
-            .iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(caps.formats[0]);
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width.max(1),
-            height: size.height.max(1),
-            present_mode: wgpu::PresentMode::Fifo,
-            alpha_mode: caps.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
-        };
-        surface.configure(&device, &config);
-
-        // Storage texture for compute
-        let storage_format = wgpu::TextureFormat::Rgba8Unorm;
-        let (storage_tex, storage_view) =
-            create_storage_texture(&device, config.width, config.height, storage_format);
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
-        });
+/// `camera.json` lives next to the executable so it's found regardless of
+/// the working directory the app was launched from.
+fn camera_pose_path() -> PathBuf {
+    std::env::current_exe()
+        .map(|exe| exe.with_file_name("camera.json"))
+        .unwrap_or_else(|_| PathBuf::from("camera.json"))
+}
 
-        // Shaders
-        let trace_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("trace.wgsl"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/trace.wgsl").into()),
-        });
-        let blit_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("blit.wgsl"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/blit.wgsl").into()),
-        });
+/// Records the window's geometry and the camera for the next run.
+fn save_session(win: &Window, st: &GpuState) {
+    let size = win.inner_size();
+    let session = Session {
+        position: win.outer_position().ok().map(|p| [p.x, p.y]),
+        size: [size.width, size.height],
+        maximized: win.is_maximized(),
+        camera: st.camera_ctrl.pose(),
+    };
+    let path = Session::default_path();
+    match session.save(&path) {
+        Ok(()) => log::debug!("saved session to {}", path.display()),
+        Err(e) => log::warn!("failed to save {}: {e}", path.display()),
+    }
+}
 
-        // Camera UBO
-        let camera_ctrl = CameraCtrl::new();
-        let (view_inv, proj_inv) = compute_camera_mats(&camera_ctrl, config.width, config.height);
-        let ubo = CameraUbo {
-            view_inv: view_inv.to_cols_array_2d(),
-            proj_inv: proj_inv.to_cols_array_2d(),
-            params: [config.width as f32, config.height as f32, 0.0, 0.0],
-        };
-        let camera_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("camera_ubo"),
-            contents: bytemuck::bytes_of(&ubo),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+/// The last run's session, if there is a readable one.
+fn load_session() -> Option<Session> {
+    let path = Session::default_path();
+    match Session::load(&path) {
+        Ok(session) => Some(session),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            log::warn!("could not load {}: {e}; starting fresh", path.display());
+            None
+        }
+    }
+}
 
-        // Compute pipeline
-        let compute_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("compute_bgl"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: wgpu::StorageTextureAccess::WriteOnly,
-                        format: storage_format,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-        });
-        let compute_pl = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("compute_pl"),
-            bind_group_layouts: &[&compute_bgl],
-            push_constant_ranges: &[],
-        });
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("trace_compute"),
-            layout: Some(&compute_pl),
-            module: &trace_module,
-            entry_point: Some("main"),
-            cache: None,
-            compilation_options: Default::default(),
-        });
-        let compute_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("compute_bg"),
-            layout: &compute_bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&storage_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: camera_buf.as_entire_binding(),
-                },
-            ],
-        });
+/// Where to reopen a window saved at `position` with `size`: there, if the
+/// middle of its title bar is still on a connected monitor, or else centered
+/// on the primary monitor, e.g. after the one it was on was unplugged.
+fn restored_position(elwt: &ActiveEventLoop, position: [i32; 2], size: [u32; 2]) -> Option<PhysicalPosition<i32>> {
+    let [x, y] = position;
+    let [width, height] = size.map(|d| d as i32);
+    // A point just below the top edge, in the middle of the title bar.
+    let grab = (x + width / 2, y + 16);
+    let on_screen = elwt.available_monitors().any(|monitor| {
+        let (origin, extent) = (monitor.position(), monitor.size());
+        (origin.x..origin.x + extent.width as i32).contains(&grab.0)
+            && (origin.y..origin.y + extent.height as i32).contains(&grab.1)
+    });
+    if on_screen {
+        return Some(PhysicalPosition::new(x, y));
+    }
+    log::info!("last window position is off-screen; centering the window");
+    let monitor = elwt.primary_monitor().or_else(|| elwt.available_monitors().next())?;
+    let (origin, extent) = (monitor.position(), monitor.size());
+    Some(PhysicalPosition::new(
+        origin.x + (extent.width as i32 - width).max(0) / 2,
+        origin.y + (extent.height as i32 - height).max(0) / 2,
+    ))
+}
 
-        // Render pipeline (fullscreen triangle)
-        let render_bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("render_bgl"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-        });
-        let render_pl = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("render_pl"),
-            bind_group_layouts: &[&render_bgl],
-            push_constant_ranges: &[],
-        });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("blit_pipeline"),
-            layout: Some(&render_pl),
-            vertex: wgpu::VertexState {
-                module: &blit_module,
-                entry_point: Some("vs"),
-                buffers: &[],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &blit_module,
-                entry_point: Some("fs"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-            cache: None,
-        });
-        let render_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("render_bg"),
-            layout: &render_bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&storage_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-        });
+// ---------- Turntable export ----------
+/// Fixed per-frame time step used while recording, so exports are
+/// deterministic regardless of how long each frame takes to render.
+const RECORD_DT: f32 = 1.0 / 30.0;
 
-        Self {
-            device,
-            queue,
-            config,
-            size,
-            storage_tex,
-            storage_view,
-            sampler,
-            camera_ctrl,
-            camera_buf,
-            compute_bgl,
-            compute_bg,
-            compute_pipeline,
-            render_bgl,
-            render_bg,
-            render_pipeline,
-        }
+/// One full orbit rendered as an image sequence. Frames sample yaw over
+/// [0, 2π) so that frame `frames` would coincide with frame 0, and the
+/// sequence loops seamlessly when played back.
+struct Turntable {
+    frames: u32,
+    out_dir: PathBuf,
+    start_yaw: f32,
+}
+
+impl Turntable {
+    fn new(frames: u32, out_dir: PathBuf) -> Self {
+        Self { frames, out_dir, start_yaw: 0.0 }
     }
 
-    fn resize(&mut self, surface: &wgpu::Surface<'_>, new_size: PhysicalSize<u32>) {
-        if new_size.width == 0 || new_size.height == 0 {
-            return;
+    /// Parses the `frames=N` / `out=dir` options that may follow `--turntable`.
+    fn parse_option(&mut self, opt: &str) -> bool {
+        match opt.split_once('=') {
+            Some(("frames", v)) => match v.parse::<u32>() {
+                Ok(n) if n > 0 => self.frames = n,
+                _ => log::warn!("turntable frames expects a positive integer, got {v}"),
+            },
+            Some(("out", v)) => self.out_dir = PathBuf::from(v),
+            _ => return false,
         }
-        self.size = new_size;
-        self.config.width = new_size.width;
-        self.config.height = new_size.height;
-        surface.configure(&self.device, &self.config);
-
-        let (tex, view) = create_storage_texture(
-            &self.device,
-            self.config.width,
-            self.config.height,
-            wgpu::TextureFormat::Rgba8Unorm,
-        );
-        self.storage_tex = tex;
-        self.storage_view = view;
-
-        self.compute_bg = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("compute_bg"),
-            layout: &self.compute_bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&self.storage_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: self.camera_buf.as_entire_binding(),
-                },
-            ],
-        });
-        self.render_bg = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("render_bg"),
-            layout: &self.render_bgl,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&self.storage_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.sampler),
-                },
-            ],
-        });
+        true
+    }
 
-        self.update_camera_buffer(0.0);
+    fn yaw(&self, index: u32) -> f32 {
+        self.start_yaw + 2.0 * PI * index as f32 / self.frames as f32
     }
 
-    fn update_camera_buffer(&mut self, time: f32) {
-        let (view_inv, proj_inv) =
-            compute_camera_mats(&self.camera_ctrl, self.config.width, self.config.height);
-        let ubo = CameraUbo {
-            view_inv: view_inv.to_cols_array_2d(),
-            proj_inv: proj_inv.to_cols_array_2d(),
-            params: [self.config.width as f32, self.config.height as f32, time, 0.0],
-        };
-        self.queue
-            .write_buffer(&self.camera_buf, 0, bytemuck::bytes_of(&ubo));
+    fn time(&self, index: u32) -> f32 {
+        index as f32 * RECORD_DT
     }
 
-    fn render(&mut self, surface: &wgpu::Surface<'_>, time: f32) -> Result<(), wgpu::SurfaceError> {
-        self.update_camera_buffer(time);
+    fn frame_path(&self, index: u32) -> PathBuf {
+        self.out_dir.join(format!("frame_{index:04}.png"))
+    }
 
-        let frame = surface.get_current_texture()?;
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder =
-            self.device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    /// Renders and saves every frame of the orbit offscreen.
+    fn export(&mut self, st: &mut GpuState) -> Result<(), String> {
+        std::fs::create_dir_all(&self.out_dir)
+            .map_err(|e| format!("failed to create {}: {e}", self.out_dir.display()))?;
+        self.start_yaw = st.camera_ctrl.yaw;
+        for index in 0..self.frames {
+            st.camera_ctrl.snap_yaw(self.yaw(index));
+            st.render_offscreen(self.time(index));
+            let path = self.frame_path(index);
+            st.read_storage_rgba8()
+                .save_png(&path)
+                .map_err(|e| format!("failed to save {}: {e}", path.display()))?;
+            if (index + 1) % 10 == 0 || index + 1 == self.frames {
+                log::info!("turntable: {}/{} frames", index + 1, self.frames);
+            }
+        }
+        Ok(())
+    }
+}
+
+// ---------- App / ApplicationHandler ----------
+const DEFAULT_TITLE: &str = "Black Hole — wgpu27 / winit30";
+// The title also shows the frame rate and the hole's parameters, rewritten
+// at most this often.
+const TITLE_REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+const ICON_PNG: &[u8] = include_bytes!("../assets/icon.png");
+// Shader time runs at this many times wall-clock speed at most, or at
+// least its inverse; < and > halve and double it.
+const TIME_SCALE_MAX: f32 = 8.0;
+// Two left clicks at most this far apart in time and pixels make a double
+// click, which refocuses the orbit on the point under the cursor.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+const DOUBLE_CLICK_SLOP: f32 = 4.0;
+
+struct App {
+    instance: wgpu::Instance,
+    window:  Option<Arc<Window>>,
+    surface: Option<wgpu::Surface<'static>>,
+    state:   Option<GpuState>,
+    // Shader time: scaled frame deltas summed up, frozen while paused.
+    sim_time: f32,
+    time_scale: f32,
+    paused: bool,
+    last_frame: Instant,
+    frame_stats: FrameStats,
+    max_fps: Option<u32>,
+    modifiers: ModifiersState,
+    // Last known cursor position, and when and where the left button last
+    // went down, for double clicks.
+    cursor: Vec2,
+    last_click: Option<(Instant, Vec2)>,
+    config: Config,
+    title: String,
+    title_refreshed: Option<Instant>,
+    // Kept alive for the whole session: on X11/Wayland the clipboard owner
+    // has to outlive the copy for other apps to be able to paste it.
+    clipboard: Option<arboard::Clipboard>,
+    // Settings from --load, applied once the GPU state exists.
+    snapshot: Option<Snapshot>,
+    // Window geometry and camera from the last run, applied when the window
+    // opens.
+    session: Option<Session>,
+    #[cfg(feature = "gamepad")]
+    gamepad: Option<gamepad::Gamepad>,
+}
 
-        // compute
+impl App {
+    fn init_window(&mut self, elwt: &ActiveEventLoop) -> Result<(), InitError> {
+        // Create the window
+        let mut attrs = WindowAttributes::default()
+            .with_title(self.title.as_str())
+            .with_window_icon(window_icon());
+        // A size from the config or command line wins over the last run's.
+        if let (Some(w), Some(h)) = (self.config.width, self.config.height) {
+            attrs = attrs.with_inner_size(LogicalSize::new(w, h));
+        } else if let Some(session) = self.session.as_ref() {
+            attrs = attrs
+                .with_inner_size(PhysicalSize::new(session.size[0], session.size[1]))
+                .with_maximized(session.maximized);
+        }
+        if let Some(session) = self.session.as_ref()
+            && let Some(position) = session.position.and_then(|p| restored_position(elwt, p, session.size))
         {
-            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("trace_compute"),
-                timestamp_writes: None,
-            });
-            cpass.set_pipeline(&self.compute_pipeline);
-            cpass.set_bind_group(0, &self.compute_bg, &[]);
-            let wg_x = (self.size.width + 7) / 8;
-            let wg_y = (self.size.height + 7) / 8;
-            cpass.dispatch_workgroups(wg_x, wg_y, 1);
+            attrs = attrs.with_position(position);
         }
+        let win = Arc::new(elwt.create_window(attrs).map_err(InitError::Window)?);
 
-        // blit
+        // The surface holds its own reference, so it stays valid for as
+        // long as it lives without borrowing from `self`.
+        let surf = self
+            .instance
+            .create_surface(Arc::clone(&win))
+            .map_err(InitError::Surface)?;
+        self.surface = Some(surf);
+
+        // Build GPU state
+        let mut st = pollster::block_on(GpuState::new(
+            &self.instance,
+            self.surface.as_ref(),
+            win.inner_size(),
+            &self.config,
+        ))?;
+        st.set_scale_factor(win.scale_factor());
+        // As with the size, a camera set in the config wins over the last run's.
+        if let Some(session) = self.session.take()
+            && !self.config.pose_given
         {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("blit_render"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    depth_slice: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-            rpass.set_pipeline(&self.render_pipeline);
-            rpass.set_bind_group(0, &self.render_bg, &[]);
-            rpass.draw(0..3, 0..1);
+            st.camera_ctrl.set_pose(session.camera);
         }
-
-        self.queue.submit(Some(encoder.finish()));
-        frame.present();
+        if let Some(snapshot) = self.snapshot.take() {
+            st.restore(&snapshot);
+        }
+        self.window = Some(win);
+        self.state = Some(st);
+        self.sim_time = 0.0;
         Ok(())
     }
 }
 
-fn create_storage_texture(
-    device: &wgpu::Device,
-    width: u32,
-    height: u32,
-    format: wgpu::TextureFormat,
-) -> (wgpu::Texture, wgpu::TextureView) {
-    let tex = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("storage_tex"),
-        size: wgpu::Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format,
-        usage: wgpu::TextureUsages::STORAGE_BINDING
-            | wgpu::TextureUsages::TEXTURE_BINDING
-            | wgpu::TextureUsages::COPY_DST,
-        view_formats: &[],
-    });
-    let view = tex.create_view(&wgpu::TextureViewDescriptor::default());
-    (tex, view)
-}
-
-fn compute_camera_mats(ctrl: &CameraCtrl, width: u32, height: u32) -> (Mat4, Mat4) {
-    let (eye, target, up) = ctrl.eye_target_up();
-    let view = Mat4::look_at_rh(eye, target, up);
-    let view_inv = view.inverse();
-
-    let aspect = (width.max(1) as f32) / (height.max(1) as f32);
-    let proj = Mat4::perspective_rh(ctrl.fov_y, aspect, 0.1, 1000.0);
-    let proj_inv = proj.inverse();
-    (view_inv, proj_inv)
+fn window_icon() -> Option<Icon> {
+    let icon = image::load_from_memory(ICON_PNG)
+        .map_err(|e| e.to_string())
+        .and_then(|img| {
+            let rgba = img.into_rgba8();
+            let (width, height) = rgba.dimensions();
+            Icon::from_rgba(rgba.into_raw(), width, height).map_err(|e| e.to_string())
+        });
+    icon.inspect_err(|e| log::warn!("window icon unavailable: {e}")).ok()
 }
 
-// ---------- App / ApplicationHandler ----------
-struct App {
-    instance: wgpu::Instance,
-    window:  Option<&'static Window>,
-    surface: Option<wgpu::Surface<'static>>,
-    state:   Option<GpuState>,
-    start:   Instant,
+/// `title — 60 fps — a=0.70 M=0.25 — Quality`, leaving out the rate until
+/// frames have been timed and the preset until one is picked.
+fn live_title(title: &str, stats: &FrameStats, st: &GpuState) -> String {
+    let mut params = format!("a={:.2} M={:.2}", st.scene.spin(), st.scene.mass());
+    if let Some(preset) = st.quality_preset() {
+        params = format!("{params} — {preset}");
+    }
+    match stats.average_ms() {
+        Some(ms) => format!("{title} — {:.0} fps — {params}", 1000.0 / ms),
+        None => format!("{title} — {params}"),
+    }
 }
 
 impl ApplicationHandler for App {
     fn resumed(&mut self, elwt: &ActiveEventLoop) {
-        if self.window.is_none() {
-            // Create the window
-            let win = elwt
-                .create_window(WindowAttributes::default().with_title("Black Hole — wgpu27 / winit30"))
-                .expect("window");
-
-            // Leak to get &'static Window (ok for a single-window app)
-            let win_static: &'static Window = Box::leak(Box::new(win));
-            self.window = Some(win_static);
-
-            // Create surface borrowing the stored window
-            let surf = self.instance.create_surface(win_static).expect("surface");
-            self.surface = Some(surf);
-
-            // Build GPU state
-            let st = pollster::block_on(GpuState::new(
-                &self.instance,
-                win_static,
-                self.surface.as_ref().unwrap(),
-            ));
-            self.state = Some(st);
-            self.start = Instant::now();
+        let result = match (&self.window, &mut self.state) {
+            (None, _) => self.init_window(elwt),
+            // Coming back from `suspended`: the window and GPU state survive,
+            // only the surface has to be recreated and configured.
+            (Some(win), Some(st)) if self.surface.is_none() => self
+                .instance
+                .create_surface(Arc::clone(win))
+                .map(|surf| {
+                    st.resize(&surf, win.inner_size());
+                    self.surface = Some(surf);
+                    win.request_redraw();
+                })
+                .map_err(InitError::Surface),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            log::error!("{e}");
+            elwt.exit();
         }
     }
 
+    /// Mobile platforms take the native window away while suspended, so
+    /// the surface has to go with it.
+    fn suspended(&mut self, _elwt: &ActiveEventLoop) {
+        self.surface = None;
+    }
+
     fn window_event(
         &mut self,
         elwt: &ActiveEventLoop,
@@ -489,49 +367,458 @@ impl ApplicationHandler for App {
         event: winit::event::WindowEvent,
     ) {
         if let (Some(win), Some(surf), Some(st)) =
-            (self.window, self.surface.as_ref(), self.state.as_mut())
+            (self.window.as_deref(), self.surface.as_ref(), self.state.as_mut())
         {
             if window_id != win.id() { return; }
+            if let WindowEvent::CursorMoved { position, .. } = &event {
+                self.cursor = Vec2::new(position.x as f32, position.y as f32);
+            }
 
             match event {
-                WindowEvent::CloseRequested => elwt.exit(),
+                WindowEvent::CloseRequested => {
+                    save_session(win, st);
+                    elwt.exit();
+                }
                 WindowEvent::Resized(new_size) => st.resize(surf, new_size),
+                WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                    st.set_scale_factor(scale_factor);
+                    st.resize(surf, win.inner_size());
+                    win.request_redraw();
+                }
+                WindowEvent::ModifiersChanged(m) => {
+                    self.modifiers = m.state();
+                    st.camera_ctrl.boost = self.modifiers.shift_key();
+                }
+                WindowEvent::Focused(focused) => {
+                    if !focused {
+                        st.camera_ctrl.held_keys.clear();
+                        st.camera_ctrl.touches.clear();
+                    }
+                    st.paused = !focused && !self.config.render_when_unfocused;
+                    win.request_redraw();
+                }
 
-                WindowEvent::MouseInput { state: mstate, button: MouseButton::Left, .. } => {
-                    st.camera_ctrl.dragging = mstate == ElementState::Pressed;
-                    if !st.camera_ctrl.dragging { st.camera_ctrl.last_cursor = None; }
+                // Orbit (left) and pan (middle) are mutually exclusive: whichever
+                // button went down first owns the cursor until it is released.
+                WindowEvent::MouseInput { state: mstate, button: MouseButton::Left, .. } if !st.camera_ctrl.panning => {
+                    st.camera_ctrl.set_dragging(mstate == ElementState::Pressed);
+                    if mstate == ElementState::Pressed {
+                        let now = Instant::now();
+                        let double = self.last_click.is_some_and(|(at, pos)| {
+                            now - at <= DOUBLE_CLICK_TIME && pos.distance(self.cursor) <= DOUBLE_CLICK_SLOP
+                        });
+                        // A third click starts a new pair rather than refocusing again.
+                        self.last_click = (!double).then_some((now, self.cursor));
+                        if double {
+                            match st.pick(self.cursor) {
+                                Some(point) => st.camera_ctrl.focus_on(point),
+                                None => log::info!("double click: no disk under the cursor"),
+                            }
+                            win.request_redraw();
+                        }
+                    }
+                }
+                WindowEvent::MouseInput { state: mstate, button: MouseButton::Middle, .. } if !st.camera_ctrl.dragging => {
+                    st.camera_ctrl.panning = mstate == ElementState::Pressed;
+                    if !st.camera_ctrl.panning { st.camera_ctrl.last_cursor = None; }
                 }
-                WindowEvent::CursorMoved { position, .. } => {
-                    if st.camera_ctrl.dragging {
-                        let pos = Vec2::new(position.x as f32, position.y as f32);
-                        if let Some(prev) = st.camera_ctrl.last_cursor {
-                            let delta = pos - prev;
-                            let sensitivity = 0.005;
-                            st.camera_ctrl.yaw   -= delta.x * sensitivity;
-                            st.camera_ctrl.pitch -= delta.y * sensitivity;
-                            let limit = 0.995 * (PI / 2.0);
-                            st.camera_ctrl.pitch = st.camera_ctrl.pitch.clamp(-limit, limit);
-                            win.request_redraw();
-                        }
-                        st.camera_ctrl.last_cursor = Some(pos);
+                WindowEvent::CursorMoved { position, .. } if st.camera_ctrl.panning => {
+                    let pos = Vec2::new(position.x as f32, position.y as f32);
+                    if let Some(prev) = st.camera_ctrl.last_cursor {
+                        st.camera_ctrl.pan(pos - prev);
+                        win.request_redraw();
+                    }
+                    st.camera_ctrl.last_cursor = Some(pos);
+                }
+                WindowEvent::CursorMoved { position, .. } if st.camera_ctrl.dragging => {
+                    let pos = Vec2::new(position.x as f32, position.y as f32);
+                    if let Some(prev) = st.camera_ctrl.last_cursor {
+                        st.camera_ctrl.orbit(pos - prev);
+                        win.request_redraw();
+                    }
+                    st.camera_ctrl.last_cursor = Some(pos);
+                }
+                WindowEvent::Touch(touch) => {
+                    let pos = Vec2::new(touch.location.x as f32, touch.location.y as f32);
+                    if st.camera_ctrl.touch(touch.phase, touch.id, pos) {
+                        win.request_redraw();
                     }
                 }
                 WindowEvent::MouseWheel { delta, .. } => {
                     let scroll = match delta {
                         MouseScrollDelta::LineDelta(_, y) => y,
-                        MouseScrollDelta::PixelDelta(p) => (p.y as f32 / 50.0) as f32,
+                        MouseScrollDelta::PixelDelta(p) => p.y as f32 / 50.0,
                     };
-                    let factor = (1.0 - scroll * 0.1).clamp(0.2, 5.0);
-                    st.camera_ctrl.radius = (st.camera_ctrl.radius * factor).clamp(1.0, 50.0);
+                    if self.modifiers.control_key() {
+                        // Scrolling up narrows the view, like zooming a lens.
+                        st.camera_ctrl.adjust_fov(-scroll * FOV_STEP_DEG);
+                        win.request_redraw();
+                        return;
+                    }
+                    let size = win.inner_size();
+                    st.camera_ctrl.zoom_at(scroll, self.cursor, (size.width, size.height));
                     win.request_redraw();
                 }
+                WindowEvent::KeyboardInput { event, .. } => {
+                    // Ctrl chords aren't movement, but releases always go
+                    // through so no key is left held.
+                    let pressed = event.state == ElementState::Pressed;
+                    if let PhysicalKey::Code(code) = event.physical_key
+                        && !(pressed && self.modifiers.control_key())
+                    {
+                        st.camera_ctrl.set_key(code, pressed);
+                    }
+                    if !pressed {
+                        return;
+                    }
+                    match event.logical_key.as_ref() {
+                        Key::Character(c)
+                            if !event.repeat && self.modifiers.control_key() && c.eq_ignore_ascii_case("c") =>
+                        {
+                            let frame = st.read_storage_rgba8();
+                            copy_frame_to_clipboard(&mut self.clipboard, &frame);
+                        }
+                        // Ctrl+I inspects the pixel under the cursor.
+                        Key::Character(c)
+                            if !event.repeat && self.modifiers.control_key() && c.eq_ignore_ascii_case("i") =>
+                        {
+                            st.probe_pixel(self.cursor);
+                        }
+                        // Ctrl+B switches the disk between its ramp and a blackbody.
+                        Key::Character(c)
+                            if !event.repeat && self.modifiers.control_key() && c.eq_ignore_ascii_case("b") =>
+                        {
+                            st.scene.toggle_disk_emission();
+                            win.request_redraw();
+                        }
+                        // Ctrl+D shows the disk edges, escape sphere and companions.
+                        Key::Character(c)
+                            if !event.repeat && self.modifiers.control_key() && c.eq_ignore_ascii_case("d") =>
+                        {
+                            st.toggle_debug_geometry();
+                            win.request_redraw();
+                        }
+                        // Ctrl+Q steps through the Performance..Cinematic presets.
+                        Key::Character(c)
+                            if !event.repeat && self.modifiers.control_key() && c.eq_ignore_ascii_case("q") =>
+                        {
+                            st.cycle_quality_preset();
+                            win.set_title(&live_title(&self.title, &self.frame_stats, st));
+                            win.request_redraw();
+                        }
+                        // Ctrl+L toggles the vignette and chromatic aberration,
+                        // Ctrl+V and Ctrl+A step each one's strength.
+                        Key::Character(c)
+                            if !event.repeat && self.modifiers.control_key() && c.eq_ignore_ascii_case("l") =>
+                        {
+                            st.toggle_lens_effects();
+                            win.request_redraw();
+                        }
+                        Key::Character(c)
+                            if !event.repeat && self.modifiers.control_key() && c.eq_ignore_ascii_case("v") =>
+                        {
+                            st.cycle_vignette();
+                            win.request_redraw();
+                        }
+                        Key::Character(c)
+                            if !event.repeat && self.modifiers.control_key() && c.eq_ignore_ascii_case("a") =>
+                        {
+                            st.cycle_aberration();
+                            win.request_redraw();
+                        }
+                        // Ctrl+R compares the photon ring filter with the raw
+                        // trace; Ctrl+E steps its width.
+                        Key::Character(c)
+                            if !event.repeat && self.modifiers.control_key() && c.eq_ignore_ascii_case("r") =>
+                        {
+                            st.scene.toggle_ring_filter();
+                            win.request_redraw();
+                        }
+                        Key::Character(c)
+                            if !event.repeat && self.modifiers.control_key() && c.eq_ignore_ascii_case("e") =>
+                        {
+                            st.scene.cycle_ring_filter_width();
+                            win.request_redraw();
+                        }
+                        // F freezes the camera uniform to debug the trace shader.
+                        Key::Character("f" | "F") if !event.repeat => {
+                            st.toggle_camera_freeze();
+                            win.request_redraw();
+                        }
+                        Key::Character("p" | "P") if !event.repeat => {
+                            save_screenshot(&st.read_storage_rgba8(), &st.snapshot());
+                        }
+                        Key::Character("l" | "L") if !event.repeat => {
+                            self.max_fps = next_fps_cap(self.max_fps);
+                            match self.max_fps {
+                                Some(fps) => log::info!("frame-rate cap: {fps} fps"),
+                                None => log::info!("frame-rate cap: off"),
+                            }
+                        }
+                        Key::Character("v" | "V") if !event.repeat => {
+                            st.toggle_vsync(surf);
+                            win.request_redraw();
+                        }
+                        Key::Character("-") => {
+                            st.step_resolution_scale(-0.25);
+                            win.request_redraw();
+                        }
+                        Key::Character("=" | "+") => {
+                            st.step_resolution_scale(0.25);
+                            win.request_redraw();
+                        }
+                        Key::Character(",") => {
+                            st.scale_exposure(0.5);
+                            win.request_redraw();
+                        }
+                        Key::Character(".") => {
+                            st.scale_exposure(2.0);
+                            win.request_redraw();
+                        }
+                        Key::Character(c @ ("<" | ">")) => {
+                            let factor = if c == ">" { 2.0 } else { 0.5 };
+                            self.time_scale =
+                                (self.time_scale * factor).clamp(1.0 / TIME_SCALE_MAX, TIME_SCALE_MAX);
+                            log::info!("time scale: {}x", self.time_scale);
+                        }
+                        Key::Named(NamedKey::Space) if !event.repeat => {
+                            self.paused = !self.paused;
+                            log::info!("time: {}", if self.paused { "paused" } else { "running" });
+                            win.request_redraw();
+                        }
+                        Key::Character("b" | "B") if !event.repeat => {
+                            st.toggle_bloom();
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::PageUp) => {
+                            st.scene.scale_mass(1.25);
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::PageDown) => {
+                            st.scene.scale_mass(0.8);
+                            win.request_redraw();
+                        }
+                        // F4 splits the view into an A/B comparison, F8 picks
+                        // the parameter the right half changes.
+                        Key::Named(NamedKey::F4) if !event.repeat => {
+                            st.toggle_split();
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::F8) if !event.repeat => {
+                            st.cycle_comparison();
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::F1) if !event.repeat => {
+                            st.cycle_sky();
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::F12) if !event.repeat => {
+                            st.cycle_sharpen();
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::F2) if !event.repeat => {
+                            st.toggle_crosshair();
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::F3) if !event.repeat => {
+                            st.scene.cycle_debug_mode();
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::Insert) => {
+                            st.companions.add();
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::Delete) => {
+                            st.companions.remove();
+                            win.request_redraw();
+                        }
+                        // \ letterboxes the image to the next fixed aspect ratio.
+                        Key::Character("\\") if !event.repeat => {
+                            st.cycle_aspect_lock();
+                            win.request_redraw();
+                        }
+                        Key::Character("/") if !event.repeat => {
+                            st.scene.cycle_shadow_softness();
+                            win.request_redraw();
+                        }
+                        Key::Character("k" | "K") if !event.repeat => {
+                            st.scene.toggle_disk();
+                            win.request_redraw();
+                        }
+                        Key::Character("y" | "Y") if !event.repeat => {
+                            st.scene.toggle_volumetric();
+                            win.request_redraw();
+                        }
+                        // F6/F7 thin and thicken the volumetric disk, F10/F11
+                        // make its noise coarser and finer.
+                        Key::Named(key @ (NamedKey::F6 | NamedKey::F7)) => {
+                            st.scene.step_disk_thickness(if key == NamedKey::F7 { 1 } else { -1 });
+                            win.request_redraw();
+                        }
+                        Key::Named(key @ (NamedKey::F10 | NamedKey::F11)) => {
+                            st.scene.step_noise_scale(if key == NamedKey::F11 { 1 } else { -1 });
+                            win.request_redraw();
+                        }
+                        // 1-6 glide to the preset views.
+                        Key::Character(c @ ("1" | "2" | "3" | "4" | "5" | "6")) if !event.repeat => {
+                            st.camera_ctrl.go_to_preset(c.parse::<usize>().unwrap() - 1);
+                            win.request_redraw();
+                        }
+                        // 7/8 move the disk's inner edge, 9/0 its outer edge.
+                        Key::Character(c @ ("7" | "8" | "9" | "0")) => {
+                            let (inner, outer) = match c {
+                                "7" => (-1.0, 0.0),
+                                "8" => (1.0, 0.0),
+                                "9" => (0.0, -1.0),
+                                _ => (0.0, 1.0),
+                            };
+                            st.scene.step_disk_radii(inner, outer);
+                            win.request_redraw();
+                        }
+                        Key::Character("c" | "C") if !event.repeat => {
+                            st.cycle_disk_ramp();
+                            win.request_redraw();
+                        }
+                        Key::Character("z" | "Z") if !event.repeat => {
+                            st.scene.cycle_redshift_strength();
+                            win.request_redraw();
+                        }
+                        Key::Character("x" | "X") if !event.repeat => {
+                            st.scene.toggle_redshift_mode();
+                            win.request_redraw();
+                        }
+                        Key::Character("n" | "N") => {
+                            st.scene.step_spin(-1.0);
+                            win.request_redraw();
+                        }
+                        Key::Character("m" | "M") => {
+                            st.scene.step_spin(1.0);
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::Home) => {
+                            st.scene.step_max_steps(1);
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::End) => {
+                            st.scene.step_max_steps(-1);
+                            win.request_redraw();
+                        }
+                        // J/U pull the focal plane closer or push it away,
+                        // ; and ' close and open the aperture.
+                        Key::Character("j" | "J") => {
+                            st.camera_ctrl.step_focus(-1);
+                            win.request_redraw();
+                        }
+                        Key::Character("u" | "U") => {
+                            st.camera_ctrl.step_focus(1);
+                            win.request_redraw();
+                        }
+                        Key::Character(";") => {
+                            st.camera_ctrl.step_aperture(-1.0);
+                            win.request_redraw();
+                        }
+                        Key::Character("'") => {
+                            st.camera_ctrl.step_aperture(1.0);
+                            win.request_redraw();
+                        }
+                        Key::Character("t" | "T") if !event.repeat => {
+                            st.camera_ctrl.toggle_auto_orbit();
+                            win.request_redraw();
+                        }
+                        Key::Character("i" | "I") if !event.repeat => {
+                            st.toggle_jitter();
+                            win.request_redraw();
+                        }
+                        Key::Character("o" | "O") if !event.repeat => {
+                            st.camera_ctrl.toggle_projection();
+                            win.request_redraw();
+                        }
+                        Key::Character("g" | "G") if !event.repeat => {
+                            st.toggle_gizmo();
+                            win.request_redraw();
+                        }
+                        Key::Character("h" | "H") if !event.repeat => {
+                            st.toggle_hud();
+                            win.request_redraw();
+                        }
+                        Key::Character("r" | "R") if !event.repeat => {
+                            st.camera_ctrl.reset();
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::F5) if !event.repeat => {
+                            let path = camera_pose_path();
+                            match st.camera_ctrl.save(&path) {
+                                Ok(()) => log::info!("saved camera to {}", path.display()),
+                                Err(e) => log::warn!("failed to save {}: {e}", path.display()),
+                            }
+                        }
+                        Key::Named(NamedKey::F9) if !event.repeat => {
+                            let path = camera_pose_path();
+                            match st.camera_ctrl.load(&path) {
+                                Ok(()) => {
+                                    log::info!("loaded camera from {}", path.display());
+                                    win.request_redraw();
+                                }
+                                Err(e) => log::warn!("could not load {}: {e}; keeping current camera", path.display()),
+                            }
+                        }
+                        Key::Character("[") => {
+                            st.camera_ctrl.adjust_fov(-FOV_STEP_DEG);
+                            win.request_redraw();
+                        }
+                        Key::Character("]") => {
+                            st.camera_ctrl.adjust_fov(FOV_STEP_DEG);
+                            win.request_redraw();
+                        }
+                        Key::Named(NamedKey::Backspace) => {
+                            st.camera_ctrl.fov_y = DEFAULT_FOV_Y_DEG.to_radians();
+                            win.request_redraw();
+                        }
+                        _ => {}
+                    }
+                }
+                // Minimized windows have nothing to present to.
+                WindowEvent::RedrawRequested if is_minimized(win) => {}
+                WindowEvent::RedrawRequested if st.device_lost().is_some() => {
+                    // Rebuild on a new device, keeping the camera where it was.
+                    log::warn!("rebuilding the renderer after losing the GPU device");
+                    match pollster::block_on(st.recover(&self.instance, Some(surf), &self.config)) {
+                        Ok(()) => win.request_redraw(),
+                        Err(e) => {
+                            log::error!("{e}");
+                            elwt.exit();
+                        }
+                    }
+                }
                 WindowEvent::RedrawRequested => {
-                    let t = self.start.elapsed().as_secs_f32();
+                    let now = Instant::now();
+                    let frame_time = now - self.last_frame;
+                    self.frame_stats.record(frame_time);
+                    self.last_frame = now;
+                    st.update(frame_time);
+                    st.update_hud(&self.frame_stats);
+                    if self.title_refreshed.is_none_or(|t| now - t >= TITLE_REFRESH_INTERVAL) {
+                        self.title_refreshed = Some(now);
+                        win.set_title(&live_title(&self.title, &self.frame_stats, st));
+                    }
+                    if !self.paused {
+                        self.sim_time += frame_time.as_secs_f32() * self.time_scale;
+                    }
+                    let t = self.sim_time;
                     if let Err(e) = st.render(surf, t) {
                         match e {
-                            wgpu::SurfaceError::Lost => st.resize(surf, st.size),
+                            // The surface no longer matches the window, e.g.
+                            // after a resize or a move to another monitor:
+                            // reconfigure at the current size and try again.
+                            wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated => {
+                                st.resize(surf, win.inner_size());
+                                win.request_redraw();
+                            }
+                            // The compositor didn't hand out a frame in time;
+                            // skip this one.
+                            wgpu::SurfaceError::Timeout => win.request_redraw(),
                             wgpu::SurfaceError::OutOfMemory => elwt.exit(),
-                            _ => eprintln!("{e:?}"),
+                            _ => log::error!("surface error: {e}"),
                         }
                     }
                 }
@@ -540,21 +827,482 @@ impl ApplicationHandler for App {
         }
     }
 
-    fn about_to_wait(&mut self, _elwt: &ActiveEventLoop) {
-        if let Some(win) = self.window {
+    fn about_to_wait(&mut self, elwt: &ActiveEventLoop) {
+        // Sleep until the next event unless something below wants a frame.
+        elwt.set_control_flow(ControlFlow::Wait);
+        #[cfg(feature = "gamepad")]
+        if let (Some(pad), Some(st)) = (self.gamepad.as_mut(), self.state.as_mut()) {
+            pad.poll(&mut st.camera_ctrl);
+            // Pads don't wake the event loop, so keep checking on a timer.
+            elwt.set_control_flow(ControlFlow::WaitUntil(Instant::now() + gamepad::POLL_INTERVAL));
+        }
+        if let (Some(win), Some(st)) = (self.window.as_deref(), self.state.as_ref()) {
+            // Input handlers request their own redraws; only keep the loop
+            // running while the camera moves, something animates, the
+            // accumulation hasn't converged or a resize is settling.
+            if !st.needs_redraw() || is_minimized(win) {
+                return;
+            }
+            if let Some(fps) = self.max_fps {
+                // Wake up when the next frame is due instead of blocking the
+                // thread, so input keeps flowing in between.
+                let next_frame = self.last_frame + Duration::from_secs_f64(1.0 / fps as f64);
+                if Instant::now() < next_frame {
+                    elwt.set_control_flow(ControlFlow::WaitUntil(next_frame));
+                    return;
+                }
+            }
             win.request_redraw();
         }
     }
 }
 
+/// Some platforms report minimizing only as a zero-sized window.
+fn is_minimized(win: &Window) -> bool {
+    let size = win.inner_size();
+    win.is_minimized().unwrap_or(false) || size.width == 0 || size.height == 0
+}
+
+const FPS_CAPS: [u32; 4] = [30, 60, 120, 144];
+
+/// Steps through `FPS_CAPS` and then back to uncapped.
+fn next_fps_cap(current: Option<u32>) -> Option<u32> {
+    match current {
+        None => Some(FPS_CAPS[0]),
+        Some(fps) => FPS_CAPS.iter().copied().find(|&cap| cap > fps),
+    }
+}
+
+// ---------- CLI ----------
+struct Args {
+    validation: bool,
+    max_fps: Option<u32>,
+    turntable: Option<Turntable>,
+    headless: bool,
+    // Frames to time per resolution when benchmarking.
+    bench: Option<u32>,
+    width: Option<u32>,
+    height: Option<u32>,
+    out: PathBuf,
+    config: Option<PathBuf>,
+    background: Option<PathBuf>,
+    skybox: Option<PathBuf>,
+    title: Option<String>,
+    holes: Vec<HoleConfig>,
+    load: Option<PathBuf>,
+    seed: Option<u32>,
+    // Samples to accumulate in a headless render before saving it.
+    frames: Option<u32>,
+    // Reference image the headless render is diffed against, and the mean
+    // difference above which the run fails.
+    compare: Option<PathBuf>,
+    threshold: f32,
+    // Trace shader variant, its entry point and override constants; see
+    // `Config::trace_shader`.
+    trace_shader: Option<PathBuf>,
+    entry_point: Option<String>,
+    constants: Vec<(String, f64)>,
+    // Backends and adapter to use; see `Config::backend`.
+    backend: Option<String>,
+    adapter_name: Option<String>,
+    storage_format: Option<StorageFormat>,
+}
+
+/// Parses `NAME=VALUE` for `--constant`.
+fn parse_constant(value: Option<String>) -> Option<(String, f64)> {
+    let parsed = value.and_then(|v| {
+        let (name, value) = v.split_once('=')?;
+        Some((name.trim().to_owned(), value.trim().parse::<f64>().ok()?))
+    });
+    if parsed.is_none() {
+        log::warn!("--constant expects NAME=VALUE");
+    }
+    parsed
+}
+
+/// Parses `x,y,z,mass` for `--hole`.
+fn parse_hole(value: Option<String>) -> Option<HoleConfig> {
+    let parsed = value.and_then(|v| {
+        let nums = v.split(',').map(|n| n.trim().parse::<f32>().ok()).collect::<Option<Vec<_>>>()?;
+        match nums[..] {
+            [x, y, z, mass] if mass > 0.0 => Some(HoleConfig { position: [x, y, z], mass }),
+            _ => None,
+        }
+    });
+    if parsed.is_none() {
+        log::warn!("--hole expects x,y,z,mass");
+    }
+    parsed
+}
+
+/// Parses the value following `flag` as a positive integer.
+fn positive_u32(flag: &str, value: Option<String>) -> Option<u32> {
+    match value.map(|v| v.parse::<u32>()) {
+        Some(Ok(n)) if n > 0 => Some(n),
+        _ => {
+            log::warn!("{flag} expects a positive integer");
+            None
+        }
+    }
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut args = Self {
+            validation: false,
+            max_fps: None,
+            turntable: None,
+            headless: false,
+            bench: None,
+            width: None,
+            height: None,
+            out: PathBuf::from("frame.png"),
+            config: None,
+            background: None,
+            skybox: None,
+            title: None,
+            holes: Vec::new(),
+            load: None,
+            seed: None,
+            frames: None,
+            compare: None,
+            threshold: DEFAULT_COMPARE_THRESHOLD,
+            trace_shader: None,
+            entry_point: None,
+            constants: Vec::new(),
+            backend: None,
+            adapter_name: None,
+            storage_format: None,
+        };
+        let mut out_dir = None;
+        let mut it = std::env::args().skip(1).peekable();
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--validation" => args.validation = true,
+                // Already applied by `init_logging`.
+                "-v" | "--verbose" => {}
+                "--max-fps" => args.max_fps = positive_u32(&arg, it.next()),
+                "--headless" => args.headless = true,
+                "--width" => args.width = positive_u32(&arg, it.next()),
+                "--height" => args.height = positive_u32(&arg, it.next()),
+                "--title" => match it.next() {
+                    Some(title) => args.title = Some(title),
+                    None => log::warn!("--title expects a window title"),
+                },
+                "--out-dir" => match it.next() {
+                    Some(dir) => out_dir = Some(PathBuf::from(dir)),
+                    None => log::warn!("--out-dir expects a directory"),
+                },
+                "--config" => match it.next() {
+                    Some(path) => args.config = Some(PathBuf::from(path)),
+                    None => log::warn!("--config expects a file path"),
+                },
+                "--background" => match it.next() {
+                    Some(path) => args.background = Some(PathBuf::from(path)),
+                    None => log::warn!("--background expects an image path"),
+                },
+                "--skybox" => match it.next() {
+                    Some(dir) => args.skybox = Some(PathBuf::from(dir)),
+                    None => log::warn!("--skybox expects a folder of cube faces"),
+                },
+                "--load" => match it.next() {
+                    Some(path) => args.load = Some(PathBuf::from(path)),
+                    None => log::warn!("--load expects a screenshot .json"),
+                },
+                "--hole" => args.holes.extend(parse_hole(it.next())),
+                "--seed" => match it.next().map(|v| v.parse::<u32>()) {
+                    Some(Ok(seed)) => args.seed = Some(seed),
+                    _ => log::warn!("--seed expects a non-negative integer"),
+                },
+                "--frames" => args.frames = positive_u32(&arg, it.next()),
+                "--compare" => match it.next() {
+                    Some(path) => args.compare = Some(PathBuf::from(path)),
+                    None => log::warn!("--compare expects a reference image"),
+                },
+                "--threshold" => match it.next().map(|v| v.parse::<f32>()) {
+                    Some(Ok(t)) if t >= 0.0 => args.threshold = t,
+                    _ => log::warn!("--threshold expects a non-negative number"),
+                },
+                "--trace-shader" => match it.next() {
+                    Some(path) => args.trace_shader = Some(PathBuf::from(path)),
+                    None => log::warn!("--trace-shader expects a WGSL file"),
+                },
+                "--entry-point" => match it.next() {
+                    Some(name) => args.entry_point = Some(name),
+                    None => log::warn!("--entry-point expects a function name"),
+                },
+                "--constant" => args.constants.extend(parse_constant(it.next())),
+                "--backend" => match it.next() {
+                    Some(list) => args.backend = Some(list),
+                    None => log::warn!("--backend expects vulkan, dx12, metal or gl"),
+                },
+                "--storage-format" => match it.next().map(|v| v.parse::<StorageFormat>()) {
+                    Some(Ok(format)) => args.storage_format = Some(format),
+                    Some(Err(e)) => log::warn!("--storage-format: {e}"),
+                    None => log::warn!("--storage-format expects rgba8unorm, rgba16float or rgba32float"),
+                },
+                "--adapter-name" => match it.next() {
+                    Some(name) => args.adapter_name = Some(name),
+                    None => log::warn!("--adapter-name expects part of an adapter name"),
+                },
+                "--out" => match it.next() {
+                    Some(path) => args.out = PathBuf::from(path),
+                    None => log::warn!("--out expects a file path"),
+                },
+                // `--bench` or `--bench N` frames per resolution.
+                "--bench" => {
+                    let frames = it.next_if(|a| a.parse::<u32>().is_ok()).map(|n| n.parse::<u32>().unwrap());
+                    args.bench = Some(frames.unwrap_or(BENCH_DEFAULT_FRAMES).max(1));
+                }
+                "--turntable" => {
+                    let mut tt = Turntable::new(240, PathBuf::from("turntable"));
+                    // Either `--turntable N` or `--turntable frames=N out=dir`.
+                    if let Some(n) = it.next_if(|a| a.parse::<u32>().is_ok()) {
+                        tt.frames = n.parse::<u32>().unwrap().max(1);
+                    }
+                    while let Some(opt) = it.next_if(|a| a.contains('=')) {
+                        if !tt.parse_option(&opt) {
+                            log::warn!("ignoring unknown turntable option: {opt}");
+                        }
+                    }
+                    args.turntable = Some(tt);
+                }
+                other => log::warn!("ignoring unknown argument: {other}"),
+            }
+        }
+        if let (Some(tt), Some(dir)) = (args.turntable.as_mut(), out_dir) {
+            tt.out_dir = dir;
+        }
+        args
+    }
+
+    fn instance_flags(&self) -> wgpu::InstanceFlags {
+        // Backend validation layers and debug labels cost frame time, so they
+        // stay off unless asked for (WGPU_VALIDATION / WGPU_DEBUG still apply).
+        let flags = if self.validation {
+            wgpu::InstanceFlags::VALIDATION | wgpu::InstanceFlags::DEBUG
+        } else {
+            wgpu::InstanceFlags::empty()
+        };
+        flags.with_env()
+    }
+}
+
+// Output sizes `--bench` times the trace at.
+const BENCH_RESOLUTIONS: [(&str, u32, u32); 4] = [
+    ("720p", 1280, 720),
+    ("1080p", 1920, 1080),
+    ("1440p", 2560, 1440),
+    ("4K", 3840, 2160),
+];
+const BENCH_DEFAULT_FRAMES: u32 = 10;
+
+/// Times the trace offscreen at each of `BENCH_RESOLUTIONS` with the
+/// config's scene and camera, and prints a table to stdout. Each size gets
+/// a fresh headless `GpuState`, so workgroup selection runs per size too.
+fn run_bench(instance: &wgpu::Instance, frames: u32, config: &Config, snapshot: Option<&Snapshot>) {
+    println!("{:>6} {:>11} {:>10} {:>10}", "size", "trace", "ms/frame", "Mrays/s");
+    for (name, width, height) in BENCH_RESOLUTIONS {
+        let size = PhysicalSize::new(width, height);
+        let mut st = match pollster::block_on(GpuState::new(instance, None, size, config)) {
+            Ok(st) => st,
+            Err(e) => {
+                log::error!("{e}");
+                std::process::exit(1);
+            }
+        };
+        if let Some(snapshot) = snapshot {
+            st.restore(snapshot);
+        }
+        let ((trace_w, trace_h), per_frame) = st.bench_trace(frames);
+        let ms = per_frame.as_secs_f64() * 1000.0;
+        let mrays = (trace_w * trace_h) as f64 / per_frame.as_secs_f64() / 1e6;
+        println!("{name:>6} {:>11} {ms:>10.2} {mrays:>10.2}", format!("{trace_w}x{trace_h}"));
+    }
+}
+
+/// Mean per-pixel difference from the `--compare` reference that still
+/// passes, as a fraction of full scale.
+const DEFAULT_COMPARE_THRESHOLD: f32 = 0.01;
+
+/// Diffs `frame` against the image at `reference`; false if it can't be
+/// read, differs in size, or differs by more than `threshold` on average.
+fn matches_reference(frame: &FrameCapture, reference: &Path, threshold: f32) -> bool {
+    let expected = match FrameCapture::load(reference) {
+        Ok(expected) => expected,
+        Err(e) => {
+            log::error!("failed to load {}: {e}", reference.display());
+            return false;
+        }
+    };
+    match frame.mean_difference(&expected) {
+        Some(diff) if diff <= threshold => {
+            log::info!("matches {} (mean difference {diff:.5} <= {threshold})", reference.display());
+            true
+        }
+        Some(diff) => {
+            log::error!("differs from {}: mean difference {diff:.5} > {threshold}", reference.display());
+            false
+        }
+        None => {
+            log::error!(
+                "{}x{} frame can't be compared with the {}x{} reference {}",
+                frame.width,
+                frame.height,
+                expected.width,
+                expected.height,
+                reference.display()
+            );
+            false
+        }
+    }
+}
+
+/// Renders one frame (or a turntable sequence) at the requested size
+/// without a window and saves it. With `--frames N` the saved image has N
+/// accumulated samples; with `--compare` the run exits with an error if it
+/// doesn't match the reference.
+fn run_headless(instance: &wgpu::Instance, args: &mut Args, config: &Config, snapshot: Option<&Snapshot>) {
+    let width = args.width.or(config.width).unwrap_or(1920);
+    let height = args.height.or(config.height).unwrap_or(1080);
+    let size = PhysicalSize::new(width, height);
+    let mut st = match pollster::block_on(GpuState::new(instance, None, size, config)) {
+        Ok(st) => st,
+        Err(e) => {
+            log::error!("{e}");
+            std::process::exit(1);
+        }
+    };
+    if let Some(snapshot) = snapshot {
+        st.restore(snapshot);
+    }
+    if let Some(tt) = args.turntable.as_mut() {
+        if let Err(e) = tt.export(&mut st) {
+            log::error!("{e}");
+            std::process::exit(1);
+        }
+        log::info!("wrote {} turntable frames to {}", tt.frames, tt.out_dir.display());
+        return;
+    }
+    for _ in 0..args.frames.unwrap_or(1) {
+        st.render_offscreen(0.0);
+    }
+    // `.exr` keeps the float trace as is; anything else is tonemapped to 8-bit.
+    let is_exr = args.out.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exr"));
+    let result = if is_exr {
+        let frame = st.read_storage_hdr();
+        frame.save_exr(&args.out).map(|()| (frame.width, frame.height)).map_err(|e| e.to_string())
+    } else {
+        let frame = st.read_storage_rgba8();
+        frame.save_png(&args.out).map(|()| (frame.width, frame.height)).map_err(|e| e.to_string())
+    };
+    match result {
+        Ok((width, height)) => log::info!("wrote {width}x{height} frame to {}", args.out.display()),
+        Err(e) => {
+            log::error!("failed to save {}: {e}", args.out.display());
+            std::process::exit(1);
+        }
+    }
+    if let Some(reference) = args.compare.as_deref()
+        && !matches_reference(&st.read_storage_rgba8(), reference, args.threshold)
+    {
+        std::process::exit(1);
+    }
+}
+
+/// Sets up `log` output: warnings from dependencies, info from this crate,
+/// one level more detail per `-v`/`--verbose`. `RUST_LOG` overrides both.
+/// Runs before argument parsing so its warnings are shown too.
+fn init_logging() {
+    let verbosity = std::env::args()
+        .skip(1)
+        .filter(|arg| arg == "-v" || arg == "--verbose")
+        .count();
+    let (deps, ours) = match verbosity {
+        0 => (log::LevelFilter::Warn, log::LevelFilter::Info),
+        1 => (log::LevelFilter::Info, log::LevelFilter::Debug),
+        _ => (log::LevelFilter::Debug, log::LevelFilter::Trace),
+    };
+    if std::env::var_os("RUST_LOG").is_some() {
+        env_logger::init();
+        return;
+    }
+    env_logger::Builder::new()
+        .filter_level(deps)
+        .filter_module(env!("CARGO_CRATE_NAME"), ours)
+        .init();
+}
+
 fn main() {
+    init_logging();
+    let mut args = Args::parse();
+    let mut config = Config::load(args.config.as_deref());
+    if let Some(path) = args.background.take() {
+        config.background = Some(path);
+    }
+    if let Some(dir) = args.skybox.take() {
+        config.skybox = Some(dir);
+    }
+    config.holes.append(&mut args.holes);
+    config.trace_shader = args.trace_shader.take().or(config.trace_shader);
+    config.trace_entry_point = args.entry_point.take().or(config.trace_entry_point);
+    config.trace_constants.extend(args.constants.drain(..));
+    config.seed = args.seed.or(config.seed);
+    config.backend = args.backend.take().or(config.backend);
+    config.adapter_name = args.adapter_name.take().or(config.adapter_name);
+    config.storage_format = args.storage_format.or(config.storage_format);
+    let snapshot = args.load.as_deref().and_then(|path| match Snapshot::load(path) {
+        Ok(snapshot) => {
+            log::info!("loaded settings from {}", path.display());
+            Some(snapshot)
+        }
+        Err(e) => {
+            log::warn!("could not load {}: {e}; using the config", path.display());
+            None
+        }
+    });
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: config.backend.as_deref().map_or(wgpu::Backends::all(), wgpu::Backends::from_comma_list),
+        flags: args.instance_flags(),
+        ..Default::default()
+    });
+    if let Some(frames) = args.bench {
+        run_bench(&instance, frames, &config, snapshot.as_ref());
+        return;
+    }
+    // Turntable export and image tests never need a window.
+    if args.headless || args.turntable.is_some() || args.frames.is_some() || args.compare.is_some() {
+        run_headless(&instance, &mut args, &config, snapshot.as_ref());
+        return;
+    }
+    // For the window, --width/--height are logical pixels like the config's.
+    config.width = args.width.or(config.width);
+    config.height = args.height.or(config.height);
+
     let event_loop = EventLoop::new().expect("event loop");
+    event_loop.set_control_flow(ControlFlow::Wait);
     let mut app = App {
-        instance: wgpu::Instance::new(&wgpu::InstanceDescriptor::default()),
+        instance,
         window: None,
         surface: None,
         state: None,
-        start: Instant::now(),
+        sim_time: 0.0,
+        time_scale: 1.0,
+        paused: false,
+        last_frame: Instant::now(),
+        frame_stats: FrameStats::new(),
+        max_fps: args.max_fps.or(config.max_fps.filter(|&fps| fps > 0)),
+        modifiers: ModifiersState::empty(),
+        cursor: Vec2::ZERO,
+        last_click: None,
+        config,
+        title: args.title.take().unwrap_or_else(|| DEFAULT_TITLE.to_owned()),
+        title_refreshed: None,
+        clipboard: None,
+        snapshot,
+        session: load_session(),
+        #[cfg(feature = "gamepad")]
+        gamepad: gamepad::Gamepad::new(),
     };
     event_loop.run_app(&mut app).expect("run_app");
 }
@@ -0,0 +1,200 @@
+use std::path::Path;
+
+use half::f16;
+
+use crate::background::srgb_to_linear;
+
+// Texels per ramp; loaded strips are resampled to this width so switching
+// ramps is a single small upload into the same texture.
+const RAMP_WIDTH: u32 = 256;
+// Temperature range of the built-in blackbody ramp, inner edge to outer.
+const BLACKBODY_KELVIN: (f32, f32) = (12000.0, 1500.0);
+
+type Texels = Vec<[f16; 4]>;
+
+fn from_fn(color: impl Fn(f32) -> [f32; 3]) -> Texels {
+    (0..RAMP_WIDTH)
+        .map(|i| {
+            let [r, g, b] = color(i as f32 / (RAMP_WIDTH - 1) as f32);
+            [r, g, b, 1.0].map(f16::from_f32)
+        })
+        .collect()
+}
+
+fn lerp3(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+/// The original white-hot to orange gradient.
+fn classic() -> Texels {
+    from_fn(|t| lerp3([1.0, 0.85, 0.6], [1.0, 0.35, 0.1], t))
+}
+
+/// sRGB color of a blackbody at `kelvin`, after Tanner Helland's fit to
+/// the CIE data, normalized so the brightest channel is 1.
+fn blackbody_rgb(kelvin: f32) -> [f32; 3] {
+    let t = kelvin / 100.0;
+    let r = if t <= 66.0 { 255.0 } else { 329.7 * (t - 60.0).powf(-0.1332) };
+    let g = if t <= 66.0 {
+        99.47 * t.ln() - 161.12
+    } else {
+        288.12 * (t - 60.0).powf(-0.0755)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.52 * (t - 10.0).ln() - 305.04
+    };
+    let rgb = [r, g, b].map(|c: f32| (c / 255.0).clamp(0.0, 1.0));
+    let max = rgb.iter().copied().fold(1e-4, f32::max);
+    rgb.map(|c| c / max)
+}
+
+/// Temperature falling off log-linearly from the inner edge outward.
+fn blackbody() -> Texels {
+    let (hot, cold) = BLACKBODY_KELVIN;
+    from_fn(|t| blackbody_rgb(hot * (cold / hot).powf(t)).map(srgb_to_linear))
+}
+
+/// Hue sweep from red at the inner edge to violet at the outer, to read
+/// radius off the image.
+fn false_color() -> Texels {
+    from_fn(|t| {
+        let hue = 0.8 * t * 6.0;
+        let x = 1.0 - (hue % 2.0 - 1.0).abs();
+        match hue as u32 {
+            0 => [1.0, x, 0.0],
+            1 => [x, 1.0, 0.0],
+            2 => [0.0, 1.0, x],
+            3 => [0.0, x, 1.0],
+            _ => [x, 0.0, 1.0],
+        }
+    })
+}
+
+/// Reads the middle row of a PNG gradient strip, left = inner edge.
+fn load(path: &Path) -> Result<Texels, String> {
+    let img = image::open(path).map_err(|e| e.to_string())?.into_rgb32f();
+    let (width, height) = img.dimensions();
+    let row = height / 2;
+    Ok(from_fn(|t| {
+        let x = (t * (width - 1) as f32).round() as u32;
+        img.get_pixel(x, row).0.map(srgb_to_linear)
+    }))
+}
+
+/// Temperature-to-color lookup for the disk, sampled in trace.wgsl by the
+/// emitter's position between the inner and outer radius. Lives in its own
+/// bind group (group 2) next to the sky's.
+pub(crate) struct DiskRamp {
+    texture: wgpu::Texture,
+    pub(crate) bgl: wgpu::BindGroupLayout,
+    pub(crate) bg: wgpu::BindGroup,
+    ramps: Vec<(String, Texels)>,
+    current: usize,
+}
+
+impl DiskRamp {
+    /// Built with the built-in ramps plus, if given, one loaded from
+    /// `custom`, which is then selected first.
+    pub(crate) fn new(device: &wgpu::Device, queue: &wgpu::Queue, custom: Option<&Path>) -> Self {
+        let mut ramps = vec![
+            ("classic".to_owned(), classic()),
+            ("blackbody".to_owned(), blackbody()),
+            ("false color".to_owned(), false_color()),
+        ];
+        let mut current = 0;
+        if let Some(path) = custom {
+            match load(path) {
+                Ok(texels) => {
+                    current = ramps.len();
+                    ramps.push((path.display().to_string(), texels));
+                }
+                Err(e) => log::warn!("failed to load disk ramp {}: {e}; using built-ins", path.display()),
+            }
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("disk_ramp_tex"),
+            size: wgpu::Extent3d { width: RAMP_WIDTH, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("disk_ramp_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bgl = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("disk_ramp_bgl"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("disk_ramp_bg"),
+            layout: &bgl,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        let ramp = Self { texture, bgl, bg, ramps, current };
+        ramp.upload(queue);
+        ramp
+    }
+
+    fn upload(&self, queue: &wgpu::Queue) {
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&self.ramps[self.current].1),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(RAMP_WIDTH * 8),
+                rows_per_image: Some(1),
+            },
+            wgpu::Extent3d { width: RAMP_WIDTH, height: 1, depth_or_array_layers: 1 },
+        );
+    }
+
+    /// Switches to the next ramp; the caller has to retrace.
+    pub(crate) fn cycle(&mut self, queue: &wgpu::Queue) {
+        self.current = (self.current + 1) % self.ramps.len();
+        self.upload(queue);
+        log::info!("disk ramp: {}", self.ramps[self.current].0);
+    }
+}
@@ -0,0 +1,461 @@
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, DiskEmission, HoleConfig, Sky};
+
+// Mass range in scene units (G = c = 1). The upper bound keeps the horizon,
+// at r = 2M, outside the closest orbit the camera can zoom to.
+const MASS_RANGE: (f32, f32) = (0.02, 0.45);
+const DEFAULT_MASS: f32 = 0.25;
+// Disk radii in scene units; the inner edge starts at the ISCO (6M).
+const DEFAULT_DISK: (f32, f32) = (6.0 * DEFAULT_MASS, 12.0 * DEFAULT_MASS);
+const DISK_RADIUS_MAX: f32 = 20.0;
+const DISK_STEP: f32 = 0.1;
+// Redshift exponents the Z key cycles through; 1 is the physical value.
+const REDSHIFT_STRENGTHS: [f32; 4] = [1.0, 2.0, 0.0, 0.5];
+// Integration step budget per ray, moved in coarse increments.
+const MAX_STEPS_RANGE: (u32, u32) = (50, 2000);
+const MAX_STEPS_INCREMENT: u32 = 50;
+// Dimensionless spin a/M; 1 would be an extremal hole with no horizon area
+// left to resolve, so stop just short of it.
+const SPIN_MAX: f32 = 0.99;
+const SPIN_STEP: f32 = 0.1;
+// Volumetric disk: full thickness and the density noise's frequency, both
+// stepped by a factor.
+const DISK_THICKNESS_RANGE: (f32, f32) = (0.02, 1.0);
+const NOISE_SCALE_RANGE: (f32, f32) = (0.5, 32.0);
+const VOLUME_STEP: f32 = 1.25;
+const DEFAULT_DISK_THICKNESS: f32 = 0.1;
+const DEFAULT_NOISE_SCALE: f32 = 4.0;
+// Widths in pixels of the shadow edge's anti-aliasing ramp that / cycles
+// through; 0 leaves the edge to accumulation.
+const SHADOW_SOFTNESS_LEVELS: [f32; 4] = [0.0, 1.0, 2.0, 4.0];
+const DEFAULT_SHADOW_SOFTNESS: f32 = 1.0;
+// Widths in pixels of the photon ring's filter that Ctrl+E cycles through.
+const RING_FILTER_WIDTHS: [f32; 4] = [0.5, 1.0, 2.0, 4.0];
+pub(crate) const DEFAULT_RING_FILTER_WIDTH: f32 = 1.0;
+// Disk coloring, indexed by `disk_emission`; the `DISK_*` constants in
+// trace.wgsl.
+const DISK_EMISSIONS: [&str; 2] = ["ramp", "blackbody"];
+const DISK_RAMP: u32 = 0;
+const DISK_BLACKBODY: u32 = 1;
+// Edge temperatures of the blackbody disk in kelvin: blue-white inside,
+// orange at the rim.
+pub(crate) const DEFAULT_DISK_TEMPS: (f32, f32) = (10000.0, 3000.0);
+const DISK_TEMP_RANGE: (f32, f32) = (500.0, 100000.0);
+// What the split-screen shows on the right, changed from the left's scene.
+pub(crate) const COMPARISONS: [&str; 4] = ["spin", "volumetric disk", "redshift mode", "half step size"];
+// What escaped rays see, indexed by `sky_mode`; the `SKY_*` constants in
+// trace.wgsl. The flat color is only a default until the config sets one.
+const SKY_MODES: [&str; 4] = ["black", "flat color", "stars", "skybox"];
+const SKY_BLACK: u32 = 0;
+pub(crate) const SKY_COLOR: u32 = 1;
+const SKY_STARS: u32 = 2;
+const SKY_SKYBOX: u32 = 3;
+const DEFAULT_SKY_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+// What the trace shader writes, indexed by `debug_mode`.
+const DEBUG_MODES: [&str; 4] = ["off", "step count", "ray direction", "hit type"];
+// Extra holes the trace shader sums over, besides the primary at the origin.
+pub(crate) const MAX_COMPANIONS: usize = 8;
+// Mass and orbit radius of companions added from the keyboard.
+const COMPANION_MASS: f32 = 0.1;
+const COMPANION_ORBIT: f32 = 3.0;
+
+/// Black-hole parameters read by the trace shader; mirrors `Scene` in
+/// trace.wgsl up to the `SamplingUbo` fields that follow it there.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+pub struct SceneUbo {
+    pub(crate) mass: f32,
+    // Flat emitting disk in the equatorial (y = 0) plane.
+    pub(crate) disk_enabled: u32,
+    pub(crate) disk_inner: f32,
+    pub(crate) disk_outer: f32,
+    // Doppler and gravitational shift of the disk's light; see trace.wgsl.
+    pub(crate) redshift_strength: f32,
+    pub(crate) redshift_mode: u32,
+    // Integration budget per ray, and each step's length as a fraction of
+    // the distance to the horizon.
+    pub(crate) max_steps: u32,
+    pub(crate) step_size: f32,
+    // Kerr spin a/M about +y; 0 is Schwarzschild.
+    pub(crate) spin: f32,
+    // Replaces the image with a diagnostic view; see `DEBUG_MODES`.
+    pub(crate) debug_mode: u32,
+    // March through a noisy slab of gas instead of hitting a flat disk.
+    #[serde(default)]
+    pub(crate) volumetric: u32,
+    #[serde(default = "default_disk_thickness")]
+    pub(crate) disk_thickness: f32,
+    #[serde(default = "default_noise_scale")]
+    pub(crate) noise_scale: f32,
+    // Anti-aliases the shadow's edge within a frame; see trace.wgsl.
+    #[serde(default = "default_shadow_softness")]
+    pub(crate) shadow_softness: f32,
+    // Edge temperatures in kelvin for the blackbody disk.
+    #[serde(default = "default_disk_temp_inner")]
+    pub(crate) disk_temp_inner: f32,
+    #[serde(default = "default_disk_temp_outer")]
+    pub(crate) disk_temp_outer: f32,
+    // Backdrop for escaped rays; see `SKY_MODES`. The color is linear HDR
+    // radiance, exposed and tonemapped like the rest of the image.
+    #[serde(default = "default_sky_color")]
+    pub(crate) sky_color: [f32; 3],
+    #[serde(default = "default_sky_mode")]
+    pub(crate) sky_mode: u32,
+    // Ramp or blackbody; see `DISK_EMISSIONS`.
+    #[serde(default)]
+    pub(crate) disk_emission: u32,
+    // Box-filters the photon ring over this many pixels while `ring_filter`
+    // is set; see trace.wgsl.
+    #[serde(default = "default_ring_filter")]
+    pub(crate) ring_filter: u32,
+    #[serde(default = "default_ring_filter_width")]
+    pub(crate) ring_filter_width: f32,
+    #[serde(skip)]
+    _pad: u32,
+}
+
+fn default_sky_color() -> [f32; 3] {
+    DEFAULT_SKY_COLOR
+}
+
+/// How this frame samples each pixel and the view it reprojects history
+/// from, the tail of `Scene` in trace.wgsl after the `SceneUbo` fields. It
+/// changes every traced frame, so it's
+/// written apart from the scene, which only changes with the settings and
+/// is what snapshots record.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct SamplingUbo {
+    // Samples already averaged into the accumulation history; 0 restarts it.
+    pub(crate) frame_index: u32,
+    // Thin-lens radius (0 is a pinhole) and distance to the focal plane.
+    pub(crate) aperture: f32,
+    pub(crate) focus_distance: f32,
+    // Nonzero to offset each sample within its pixel, so accumulation
+    // converges to an anti-aliased image.
+    pub(crate) jitter: u32,
+    // Seeds the jitter and lens sampling, so a seed reproduces an image.
+    pub(crate) seed: u32,
+    pub(crate) _pad: [u32; 3],
+    // World to clip space for the frame in the history texture, for
+    // reprojecting it into this one.
+    pub(crate) prev_view_proj: [[f32; 4]; 4],
+}
+
+impl SamplingUbo {
+    /// Where it sits in each scene buffer.
+    pub(crate) const OFFSET: u64 = std::mem::size_of::<SceneUbo>() as u64;
+
+    /// Whether two uploads sample the same image, ignoring `frame_index`.
+    pub(crate) fn same_samples(&self, other: &Self) -> bool {
+        self.aperture == other.aperture
+            && self.focus_distance == other.focus_distance
+            && self.jitter == other.jitter
+            && self.seed == other.seed
+    }
+}
+
+// Older sidecars predate `sky_mode`; they showed the stars.
+fn default_sky_mode() -> u32 {
+    SKY_STARS
+}
+
+// Older sidecars predate the volumetric disk; they had the flat one.
+fn default_disk_thickness() -> f32 {
+    DEFAULT_DISK_THICKNESS
+}
+
+fn default_noise_scale() -> f32 {
+    DEFAULT_NOISE_SCALE
+}
+
+fn default_shadow_softness() -> f32 {
+    DEFAULT_SHADOW_SOFTNESS
+}
+
+// Older sidecars predate the ring filter; they had it off.
+fn default_ring_filter() -> u32 {
+    0
+}
+
+fn default_ring_filter_width() -> f32 {
+    DEFAULT_RING_FILTER_WIDTH
+}
+
+fn default_disk_temp_inner() -> f32 {
+    DEFAULT_DISK_TEMPS.0
+}
+
+fn default_disk_temp_outer() -> f32 {
+    DEFAULT_DISK_TEMPS.1
+}
+
+impl SceneUbo {
+    pub fn new(config: &Config) -> Self {
+        let (min_steps, max_steps) = MAX_STEPS_RANGE;
+        Self {
+            mass: DEFAULT_MASS,
+            disk_enabled: 1,
+            disk_inner: DEFAULT_DISK.0,
+            disk_outer: DEFAULT_DISK.1,
+            redshift_strength: REDSHIFT_STRENGTHS[0],
+            redshift_mode: 0,
+            max_steps: config.max_steps.clamp(min_steps, max_steps),
+            step_size: config.step_size.clamp(0.005, 0.5),
+            spin: 0.0,
+            debug_mode: 0,
+            volumetric: 0,
+            disk_thickness: DEFAULT_DISK_THICKNESS,
+            noise_scale: DEFAULT_NOISE_SCALE,
+            shadow_softness: config.shadow_softness.max(0.0),
+            disk_temp_inner: config.disk_temp_inner.clamp(DISK_TEMP_RANGE.0, DISK_TEMP_RANGE.1),
+            disk_temp_outer: config.disk_temp_outer.clamp(DISK_TEMP_RANGE.0, DISK_TEMP_RANGE.1),
+            sky_color: match config.sky {
+                Some(Sky::Color(color)) => color.map(|c| c.max(0.0)),
+                _ => DEFAULT_SKY_COLOR,
+            },
+            sky_mode: match config.sky {
+                Some(Sky::Black) => SKY_BLACK,
+                Some(Sky::Color(_)) => SKY_COLOR,
+                Some(Sky::Stars) => SKY_STARS,
+                Some(Sky::Skybox) => SKY_SKYBOX,
+                None if config.skybox.is_some() => SKY_SKYBOX,
+                None => SKY_STARS,
+            },
+            disk_emission: match config.disk_emission {
+                DiskEmission::Ramp => DISK_RAMP,
+                DiskEmission::Blackbody => DISK_BLACKBODY,
+            },
+            ring_filter: (config.ring_filter_width > 0.0) as u32,
+            ring_filter_width: match config.ring_filter_width {
+                width if width > 0.0 => width,
+                _ => DEFAULT_RING_FILTER_WIDTH,
+            },
+            _pad: 0,
+        }
+    }
+
+    pub fn scale_mass(&mut self, factor: f32) {
+        let (min, max) = MASS_RANGE;
+        self.mass = (self.mass * factor).clamp(min, max);
+        log::info!("mass: {:.3} (r_s = {:.3})", self.mass, 2.0 * self.mass);
+    }
+
+    pub fn toggle_disk(&mut self) {
+        self.disk_enabled ^= 1;
+        log::info!("accretion disk: {}", if self.disk_enabled != 0 { "on" } else { "off" });
+    }
+
+    /// Moves the disk edges by whole `DISK_STEP`s, keeping the inner edge
+    /// outside the horizon and at least one step inside the outer edge.
+    pub fn step_disk_radii(&mut self, inner_steps: f32, outer_steps: f32) {
+        let horizon = self.horizon_radius();
+        self.disk_outer = (self.disk_outer + outer_steps * DISK_STEP)
+            .clamp(horizon + 2.0 * DISK_STEP, DISK_RADIUS_MAX);
+        self.disk_inner = (self.disk_inner + inner_steps * DISK_STEP)
+            .clamp(horizon + DISK_STEP, self.disk_outer - DISK_STEP);
+        log::info!("disk radii: {:.2} to {:.2}", self.disk_inner, self.disk_outer);
+    }
+
+    pub fn toggle_volumetric(&mut self) {
+        self.volumetric ^= 1;
+        log::info!("disk: {}", if self.volumetric != 0 { "volumetric" } else { "flat" });
+    }
+
+    pub fn step_disk_thickness(&mut self, steps: i32) {
+        let (min, max) = DISK_THICKNESS_RANGE;
+        self.disk_thickness = (self.disk_thickness * VOLUME_STEP.powi(steps)).clamp(min, max);
+        log::info!("disk thickness: {:.3}", self.disk_thickness);
+    }
+
+    pub fn step_noise_scale(&mut self, steps: i32) {
+        let (min, max) = NOISE_SCALE_RANGE;
+        self.noise_scale = (self.noise_scale * VOLUME_STEP.powi(steps)).clamp(min, max);
+        log::info!("disk noise scale: {:.2}", self.noise_scale);
+    }
+
+    pub fn cycle_redshift_strength(&mut self) {
+        let next = REDSHIFT_STRENGTHS
+            .iter()
+            .position(|&s| s == self.redshift_strength)
+            .map_or(0, |i| (i + 1) % REDSHIFT_STRENGTHS.len());
+        self.redshift_strength = REDSHIFT_STRENGTHS[next];
+        log::info!("redshift strength: {}", self.redshift_strength);
+    }
+
+    /// Adds `increments` steps of `MAX_STEPS_INCREMENT` to the per-ray budget.
+    pub fn step_max_steps(&mut self, increments: i32) {
+        let (min, max) = MAX_STEPS_RANGE;
+        let steps = self.max_steps as i32 + increments * MAX_STEPS_INCREMENT as i32;
+        self.max_steps = (steps.max(0) as u32).clamp(min, max);
+        log::info!("max steps: {} (step size {})", self.max_steps, self.step_size);
+    }
+
+    pub fn step_spin(&mut self, steps: f32) {
+        self.spin = (self.spin + steps * SPIN_STEP).clamp(0.0, SPIN_MAX);
+        // Snap away float drift so 0 stays exactly Schwarzschild.
+        self.spin = (self.spin * 100.0).round() / 100.0;
+        log::info!("spin: {:.2} (horizon r = {:.3})", self.spin, self.horizon_radius());
+    }
+
+    pub fn mass(&self) -> f32 {
+        self.mass
+    }
+
+    pub fn spin(&self) -> f32 {
+        self.spin
+    }
+
+    /// Outer Kerr horizon, r+ = M (1 + sqrt(1 - a^2)).
+    pub fn horizon_radius(&self) -> f32 {
+        self.mass * (1.0 + (1.0 - self.spin * self.spin).sqrt())
+    }
+
+    /// Steps to the next of `SKY_MODES`, leaving out the skybox when none
+    /// is loaded.
+    pub fn cycle_sky(&mut self, has_skybox: bool) {
+        self.sky_mode = (self.sky_mode + 1) % SKY_MODES.len() as u32;
+        if self.sky_mode == SKY_SKYBOX && !has_skybox {
+            self.sky_mode = SKY_BLACK;
+        }
+        log::info!("sky: {}", SKY_MODES[self.sky_mode as usize]);
+    }
+
+    pub fn cycle_shadow_softness(&mut self) {
+        let next = SHADOW_SOFTNESS_LEVELS
+            .iter()
+            .position(|&s| s == self.shadow_softness)
+            .map_or(0, |i| (i + 1) % SHADOW_SOFTNESS_LEVELS.len());
+        self.shadow_softness = SHADOW_SOFTNESS_LEVELS[next];
+        log::info!("shadow edge softening: {} px", self.shadow_softness);
+    }
+
+    /// Switches the photon ring filter off to compare against the raw trace.
+    pub fn toggle_ring_filter(&mut self) {
+        self.ring_filter ^= 1;
+        log::info!("photon ring filter: {}", if self.ring_filter != 0 { "on" } else { "off" });
+    }
+
+    pub fn cycle_ring_filter_width(&mut self) {
+        let next = RING_FILTER_WIDTHS
+            .iter()
+            .position(|&w| w == self.ring_filter_width)
+            .map_or(0, |i| (i + 1) % RING_FILTER_WIDTHS.len());
+        self.ring_filter_width = RING_FILTER_WIDTHS[next];
+        self.ring_filter = 1;
+        log::info!("photon ring filter: {} px", self.ring_filter_width);
+    }
+
+    pub fn cycle_debug_mode(&mut self) {
+        self.debug_mode = (self.debug_mode + 1) % DEBUG_MODES.len() as u32;
+        log::info!("debug view: {}", DEBUG_MODES[self.debug_mode as usize]);
+    }
+
+    /// A copy with one parameter changed for split-screen comparison
+    /// `variant` (an index into `COMPARISONS`).
+    pub fn compared(&self, variant: usize) -> Self {
+        let mut other = *self;
+        match variant % COMPARISONS.len() {
+            0 => other.spin = if self.spin > 0.0 { 0.0 } else { 0.9 },
+            1 => other.volumetric ^= 1,
+            2 => other.redshift_mode ^= 1,
+            _ => other.step_size *= 0.5,
+        }
+        other
+    }
+
+    pub fn toggle_disk_emission(&mut self) {
+        self.disk_emission = (self.disk_emission + 1) % DISK_EMISSIONS.len() as u32;
+        match self.disk_emission {
+            DISK_BLACKBODY => log::info!(
+                "disk emission: blackbody, {:.0} K to {:.0} K",
+                self.disk_temp_inner,
+                self.disk_temp_outer
+            ),
+            _ => log::info!("disk emission: {}", DISK_EMISSIONS[self.disk_emission as usize]),
+        }
+    }
+
+    pub fn toggle_redshift_mode(&mut self) {
+        self.redshift_mode ^= 1;
+        log::info!("redshift: {}", if self.redshift_mode == 0 { "physical" } else { "artistic" });
+    }
+}
+
+/// One extra hole; mirrors `Companion` in trace.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Pod, Zeroable)]
+pub(crate) struct Companion {
+    pub(crate) position: [f32; 3],
+    pub(crate) mass: f32,
+}
+
+/// Non-spinning holes alongside the primary, e.g. for a binary. They bend
+/// light like the primary but carry no disk. Stored in a storage buffer;
+/// mirrors `Companions` in trace.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct Companions {
+    count: u32,
+    _pad: [u32; 3],
+    holes: [Companion; MAX_COMPANIONS],
+}
+
+impl Companions {
+    pub fn new(config: &Config) -> Self {
+        Self::from_holes(&config.holes)
+    }
+
+    pub fn from_holes(holes: &[HoleConfig]) -> Self {
+        let mut companions = Self::zeroed();
+        for hole in holes.iter().take(MAX_COMPANIONS) {
+            companions.push(Companion {
+                position: hole.position,
+                mass: hole.mass.clamp(MASS_RANGE.0, MASS_RANGE.1),
+            });
+        }
+        if holes.len() > MAX_COMPANIONS {
+            log::warn!("only the first {MAX_COMPANIONS} extra holes are used");
+        }
+        companions
+    }
+
+    /// The holes in use, in config form.
+    pub fn holes(&self) -> Vec<HoleConfig> {
+        self.holes[..self.count as usize]
+            .iter()
+            .map(|hole| HoleConfig { position: hole.position, mass: hole.mass })
+            .collect()
+    }
+
+    fn push(&mut self, hole: Companion) {
+        self.holes[self.count as usize] = hole;
+        self.count += 1;
+    }
+
+    /// Adds a hole on a ring in the disk plane, spaced by golden-angle
+    /// steps so new ones don't land on top of earlier ones.
+    pub fn add(&mut self) {
+        if self.count as usize == MAX_COMPANIONS {
+            log::warn!("already at {MAX_COMPANIONS} extra holes");
+            return;
+        }
+        let angle = self.count as f32 * 2.4;
+        self.push(Companion {
+            position: [COMPANION_ORBIT * angle.cos(), 0.0, COMPANION_ORBIT * angle.sin()],
+            mass: COMPANION_MASS,
+        });
+        log::info!("extra holes: {}", self.count);
+    }
+
+    pub fn remove(&mut self) {
+        if self.count == 0 {
+            return;
+        }
+        self.count -= 1;
+        self.holes[self.count as usize] = Companion::zeroed();
+        log::info!("extra holes: {}", self.count);
+    }
+}
@@ -0,0 +1,41 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CameraPose;
+
+/// Where the window was and what the camera looked at when the app last
+/// closed, so the next run picks up there. Written to `session.json` on
+/// exit and read back at startup.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    // Outer top-left corner and inner size in physical pixels. The position
+    // is absent on platforms that don't report one (Wayland).
+    pub position: Option<[i32; 2]>,
+    pub size: [u32; 2],
+    #[serde(default)]
+    pub maximized: bool,
+    pub camera: CameraPose,
+}
+
+impl Session {
+    /// Next to the executable like `camera.json`, so it's found regardless
+    /// of the working directory the app was launched from.
+    pub fn default_path() -> PathBuf {
+        std::env::current_exe()
+            .map(|exe| exe.with_file_name("session.json"))
+            .unwrap_or_else(|_| PathBuf::from("session.json"))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+}
@@ -0,0 +1,42 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CameraPose;
+use crate::config::HoleConfig;
+use crate::scene::SceneUbo;
+
+/// Everything needed to render a frame again: camera, display and scene
+/// settings. Written as a `.json` sidecar next to each screenshot and read
+/// back with `--load`.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub camera: CameraPose,
+    pub exposure: f32,
+    pub resolution_scale: f32,
+    pub scene: SceneUbo,
+    #[serde(default)]
+    pub holes: Vec<HoleConfig>,
+    // Absent in older sidecars, which then keep the current seed.
+    #[serde(default)]
+    pub seed: Option<u32>,
+}
+
+impl Snapshot {
+    /// `image.png` -> `image.json`.
+    pub fn sidecar_path(image: &Path) -> PathBuf {
+        image.with_extension("json")
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+}
@@ -0,0 +1,212 @@
+use std::path::Path;
+
+pub(crate) fn create_storage_texture(
+    device: &wgpu::Device,
+    label: &str,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let tex = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = tex.create_view(&wgpu::TextureViewDescriptor {
+        label: Some(label),
+        ..Default::default()
+    });
+    (tex, view)
+}
+
+/// Multisampled color target matching `config`, resolved into the surface
+/// each frame. `None` when `sample_count` is 1.
+pub(crate) fn create_msaa_target(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    sample_count: u32,
+) -> Option<wgpu::TextureView> {
+    if sample_count <= 1 {
+        return None;
+    }
+    let tex = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("msaa_color"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    Some(tex.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("msaa_color"),
+        ..Default::default()
+    }))
+}
+
+/// Color target in the blit's `format` that it can draw into and a UI can
+/// then sample, for showing the image inside another renderer's frame.
+pub(crate) fn create_viewport_target(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let tex = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("viewport_color"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = tex.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("viewport_color"),
+        ..Default::default()
+    });
+    (tex, view)
+}
+
+/// Depth attachment of the blit pass: the blit writes the traced depth into
+/// it and raster overlays test against that.
+pub(crate) const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Linear view depth written by the trace alongside its color.
+pub(crate) const TRACE_DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+/// Depth target matching `config` and the color target's sample count.
+pub(crate) fn create_depth_target(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    sample_count: u32,
+) -> wgpu::TextureView {
+    let tex = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("blit_depth"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    tex.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("blit_depth"),
+        ..Default::default()
+    })
+}
+
+/// How a pass in the blit uses `DEPTH_FORMAT`. Screen-space overlays pass
+/// `(false, Always)` to draw on top regardless.
+pub(crate) fn depth_state(write: bool, compare: wgpu::CompareFunction) -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format: DEPTH_FORMAT,
+        depth_write_enabled: write,
+        depth_compare: compare,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
+
+/// CPU copy of `tonemap_aces` in blit.wgsl.
+pub(crate) fn tonemap_aces(x: f32) -> f32 {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    ((x * (a * x + b)) / (x * (c * x + d) + e)).clamp(0.0, 1.0)
+}
+
+/// CPU copy of `linear_to_srgb` in blit.wgsl.
+pub(crate) fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Turns one linear HDR texel into the 8-bit sRGB value the display shows.
+pub(crate) fn hdr_to_rgba8(texel: [f32; 4], exposure: f32) -> [u8; 4] {
+    let encode = |c: f32| (linear_to_srgb(tonemap_aces(c * exposure)) * 255.0).round() as u8;
+    [encode(texel[0]), encode(texel[1]), encode(texel[2]), 255]
+}
+
+/// An RGBA8 copy of a rendered frame, read back for screenshots and exports.
+pub struct FrameCapture {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl FrameCapture {
+    pub fn save_png(&self, path: &Path) -> image::ImageResult<()> {
+        image::save_buffer(path, &self.rgba, self.width, self.height, image::ColorType::Rgba8)
+    }
+
+    /// Reads an image saved by `save_png`, or any other `image` can decode.
+    pub fn load(path: &Path) -> image::ImageResult<Self> {
+        let img = image::open(path)?.into_rgba8();
+        Ok(Self { width: img.width(), height: img.height(), rgba: img.into_raw() })
+    }
+
+    /// Mean absolute difference from `other` over the color channels, from
+    /// 0 (identical) to 1; `None` if the sizes differ. Alpha is ignored.
+    pub fn mean_difference(&self, other: &Self) -> Option<f32> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return None;
+        }
+        let total: u64 = self
+            .rgba
+            .chunks_exact(4)
+            .zip(other.rgba.chunks_exact(4))
+            .map(|(a, b)| (0..3).map(|c| u64::from(a[c].abs_diff(b[c]))).sum::<u64>())
+            .sum();
+        let channels = u64::from(self.width) * u64::from(self.height) * 3;
+        Some(total as f32 / (channels.max(1) * 255) as f32)
+    }
+}
+
+/// A float copy of a rendered frame with its full dynamic range, for
+/// compositing.
+pub struct HdrCapture {
+    pub width: u32,
+    pub height: u32,
+    pub texels: Vec<[f32; 4]>,
+}
+
+impl HdrCapture {
+    pub fn save_exr(&self, path: &Path) -> exr::error::UnitResult {
+        let width = self.width as usize;
+        exr::prelude::write_rgba_file(path, width, self.height as usize, |x, y| {
+            let [r, g, b, a] = self.texels[y * width + x];
+            (r, g, b, a)
+        })
+    }
+}
@@ -0,0 +1,53 @@
+//! Renders on whatever adapter is available, falling back to a software one,
+//! and checks the traced image. Skipped where there is no adapter at all.
+
+use blackhole_wgpu::config::Sky;
+use blackhole_wgpu::{Config, GpuState, InitError};
+use winit::dpi::PhysicalSize;
+
+const SIZE: PhysicalSize<u32> = PhysicalSize::new(64, 40);
+const SKY: [f32; 3] = [0.2, 0.4, 0.6];
+
+/// A headless renderer looking at the hole from far enough out that its
+/// shadow sits in the middle of a plain sky, with the disk turned off.
+fn headless() -> Option<GpuState> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        flags: wgpu::InstanceFlags::VALIDATION,
+        ..Default::default()
+    });
+    let config = Config {
+        radius: 30.0,
+        pitch: 0.0,
+        sky: Some(Sky::Color(SKY)),
+        seed: Some(0),
+        ..Config::default()
+    };
+    match pollster::block_on(GpuState::new(&instance, None, SIZE, &config)) {
+        Ok(mut state) => {
+            state.scene.toggle_disk();
+            Some(state)
+        }
+        Err(e @ InitError::NoAdapter) => {
+            eprintln!("skipping: {e}");
+            None
+        }
+        Err(e) => panic!("{e}"),
+    }
+}
+
+#[test]
+fn shadow_is_dark_and_sky_shows_around_it() {
+    let Some(mut state) = headless() else { return };
+    state.render_offscreen(0.0);
+    let capture = state.read_storage_hdr();
+    assert_eq!((capture.width, capture.height), (SIZE.width, SIZE.height));
+    let texel = |x: u32, y: u32| capture.texels[(y * capture.width + x) as usize];
+
+    let center = texel(capture.width / 2, capture.height / 2);
+    assert!(center[..3].iter().all(|&c| c < 1e-3), "center isn't in the shadow: {center:?}");
+
+    let corner = texel(0, 0);
+    for (got, want) in corner.iter().zip(SKY) {
+        assert!((got - want).abs() < 1e-2, "corner isn't the sky color {SKY:?}: {corner:?}");
+    }
+}